@@ -1,7 +1,9 @@
 use cmus_notify::{
     cmus::{self, query::CmusQueryResponse},
     notification,
+    scheduler::{self, Scheduler},
     settings::Settings,
+    status_display,
 };
 
 #[cfg(feature = "debug")]
@@ -19,6 +21,16 @@ macro_rules! sleep {
 }
 
 fn main() {
+    // If cmus runs us as its status display program, just forward the status to the running daemon.
+    let args = std::env::args().collect::<Vec<_>>();
+    if status_display::is_status_display_invocation(&args) {
+        if let Err(e) = status_display::forward(&args[1..]) {
+            eprintln!("Failed to forward the status to the cmus-notify daemon: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     #[cfg(feature = "debug")]
     {
         pretty_env_logger::init();
@@ -36,56 +48,138 @@ fn main() {
         info!("Settings: {:#?}", settings);
     }
 
-    // Build the command, or use the default. (to speed up the main loop, because we don't need to build it every time)
-    let mut query_command = cmus::build_query_command(
+    // Check the templates, so the typos don't silently render as empty strings.
+    let problems = settings.validate_templates();
+    for problem in &problems {
+        eprintln!("Warning: {problem}");
+    }
+    if settings.strict && !problems.is_empty() {
+        eprintln!("Error: Refusing to start with invalid templates (strict mode).");
+        std::process::exit(1);
+    }
+
+    // Setup the connection once. (to speed up the main loop, because we don't need to build it every time)
+    let mut connection = cmus::CmusConnection::new(
         settings.remote_bin_path().as_str(),
         &settings.cmus_socket_address,
         &settings.cmus_socket_password,
     );
-    #[cfg(feature = "debug")]
-    info!("Query command built: {:?}", query_command);
 
-    let interval = settings.interval();
+    // Decides how long we sleep between the queries.
+    let mut scheduler = Scheduler::new(settings.interval());
     let link = settings.link;
+    let link_grace_period = std::time::Duration::from_millis(settings.link_grace_period());
+    let mut connection_state = cmus::ConnectionState::default();
+
+    // In the event driven mode, we wait for the status display program instead of polling cmus.
+    let listener = if settings.event_driven {
+        match status_display::StatusDisplayListener::bind() {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                eprintln!("Error: Failed to listen for the status display updates: {e}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    // The last arguments we got from the status display program, if any.
+    let mut status_display_args: Option<Vec<String>> = None;
 
     let mut notifications_handler = notification::NotificationsHandler::new(settings);
 
     // Initialize the buffer to store the response from cmus, to compare it with the next one.
     let mut previous_response = CmusQueryResponse::default();
+    // How long cmus was playing since the previous response, to tell the seeks from the normal playback.
+    let mut played = std::time::Duration::ZERO;
+    let mut previous_query_time = std::time::Instant::now();
 
     // Sleep for a 300ms before make the first query, 'cause if the demon linked with `cmus`
     // and the demon is started before `cmus` with the suggested alias, the demon 'll start and exit before `cmus`
     sleep!(300);
     loop {
         // Get the response from cmus.
-        let Ok(response) = cmus::ping_cmus(&mut query_command) else {
-            if link {
-                std::process::exit(0)
-            } else {
-                // If there is no response and the `link` mode is not active, just sleep and try again
-                sleep!(interval);
-                continue;
+        let response = match (connection.query(), status_display_args.take()) {
+            (Ok(response), _) => Some(response),
+            // If we can't reach cmus, but it told us about the change, use what it told us.
+            (Err(_), Some(args)) if previous_response != CmusQueryResponse::default() => {
+                Some(CmusQueryResponse::with_player_settings_of(
+                    status_display::to_query_output(&args, previous_response.track().ok().as_ref()),
+                    &previous_response,
+                ))
             }
+            (Err(_), _) => None,
         };
+        let mut events = Vec::with_capacity(1);
+
+        // Count the time since the previous query, if cmus was playing before or after it.
+        let since_previous_query = previous_query_time.elapsed();
+        previous_query_time = std::time::Instant::now();
+        if let Some(response) = &response {
+            if previous_response.is_playing() || response.is_playing() {
+                played += since_previous_query;
+            }
+        }
+
+        // Check if cmus started or closed.
+        if let Some(event) = connection_state.update(response.as_ref(), &previous_response) {
+            events.push(event);
+        }
 
         // Compare the response with the previous one.
-        if response != previous_response {
+        if let Some(response) = response.filter(|response| *response != previous_response) {
             // Get the events (the changes) from the response.
-            if let Ok(events) = previous_response.events(&response) {
+            if let Ok(changes) = previous_response.events(&response, played) {
                 // Update the previous response.
                 previous_response = response;
+                played = std::time::Duration::ZERO;
+                events.extend(changes);
+            }
+        }
 
-                if !events.is_empty() {
-                    match notifications_handler.show_notification(events, &previous_response) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            eprintln!("Error: {}", e);
-                        }
-                    }
+        let changed = scheduler::is_change(&events);
+        if !events.is_empty() {
+            match notifications_handler.show_notification(events) {
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Error: {}", e);
                 }
             }
         }
-        // If the track info is the same as the previous one, just sleep for a while.
-        sleep!(interval);
+
+        let disconnected_for = connection_state.disconnected_for();
+        // If the `link` mode is active, exit when cmus is gone for a while.
+        if link && disconnected_for.is_some_and(|duration| duration >= link_grace_period) {
+            std::process::exit(0)
+        }
+
+        let live = notifications_handler.has_live_notifications();
+        let wake_up = if disconnected_for.is_some() {
+            // If there is no response, just wait and try again
+            scheduler.next_reconnect()
+        } else {
+            scheduler.next_wake_up(&previous_response, changed, live)
+        };
+        match &listener {
+            // Wait for the next status change, but keep updating the live notifications, if any,
+            // and keep checking cmus in the `link` mode, to know when to exit.
+            Some(listener) => {
+                let timeout = if live || (link && disconnected_for.is_some()) {
+                    Some(std::time::Duration::from_millis(wake_up))
+                } else if link {
+                    // Nothing tells us when cmus quits, so check it every grace period.
+                    Some(link_grace_period)
+                } else {
+                    None
+                };
+                #[cfg(feature = "debug")]
+                info!("Waiting for the next status change, timeout: {:?}", timeout);
+                status_display_args = listener.wait(timeout);
+            }
+            // If the track info is the same as the previous one, just sleep for a while.
+            None => {
+                sleep!(wake_up);
+            }
+        }
     }
 }
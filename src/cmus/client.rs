@@ -0,0 +1,280 @@
+use crate::cmus::query::CmusQueryResponse;
+use crate::cmus::CmusError;
+#[cfg(feature = "debug")]
+use log::{debug, info};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The default port of the cmus TCP server.
+const DEFAULT_PORT: u16 = 3000;
+/// How long we wait for cmus to answer, before giving up.
+const IO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The address of a cmus server.
+#[derive(PartialEq, Clone)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+pub enum CmusAddress {
+    /// A Unix socket, e.g. "$XDG_RUNTIME_DIR/cmus-socket".
+    Unix(PathBuf),
+    /// A TCP address in the form "host:port", this one requires a password.
+    Tcp(String),
+}
+
+impl FromStr for CmusAddress {
+    type Err = CmusError;
+
+    /// Parse the address the same way `cmus-remote --server` does.
+    /// If the address contains a '/', it's a path to a Unix socket, otherwise it's a "host[:port]" address.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(CmusError::UnknownError("Empty socket address".to_string()));
+        }
+
+        if s.contains('/') {
+            Ok(Self::Unix(PathBuf::from(s)))
+        } else if s.contains(':') {
+            Ok(Self::Tcp(s.to_string()))
+        } else {
+            Ok(Self::Tcp(format!("{s}:{DEFAULT_PORT}")))
+        }
+    }
+}
+
+impl Default for CmusAddress {
+    /// The socket that cmus listens on by default.
+    ///
+    /// That's "$CMUS_SOCKET" if it's set, otherwise "$XDG_RUNTIME_DIR/cmus-socket",
+    /// and if there's no runtime directory, the "socket" file in the cmus config directory.
+    fn default() -> Self {
+        if let Some(socket) = std::env::var_os("CMUS_SOCKET") {
+            return Self::Unix(PathBuf::from(socket));
+        }
+
+        if let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+            return Self::Unix(PathBuf::from(runtime_dir).join("cmus-socket"));
+        }
+
+        let config_dir = match (
+            std::env::var_os("CMUS_HOME"),
+            std::env::var_os("XDG_CONFIG_HOME"),
+        ) {
+            (Some(cmus_home), _) => PathBuf::from(cmus_home),
+            (None, Some(config_home)) => PathBuf::from(config_home).join("cmus"),
+            (None, None) => PathBuf::from(std::env::var_os("HOME").unwrap_or_default())
+                .join(".config")
+                .join("cmus"),
+        };
+
+        Self::Unix(config_dir.join("socket"))
+    }
+}
+
+/// Anything we can talk to cmus through.
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+/// A native client for the cmus remote protocol.
+///
+/// It keeps the connection open between the queries, and reconnects if the connection is lost,
+/// so asking cmus for its status doesn't need a new process every time.
+pub struct CmusClient {
+    address: CmusAddress,
+    password: Option<String>,
+    connection: Option<BufReader<Box<dyn Stream>>>,
+}
+
+impl CmusClient {
+    pub fn new(address: CmusAddress, password: Option<String>) -> Self {
+        Self {
+            address,
+            password,
+            connection: None,
+        }
+    }
+
+    /// Ask cmus for its status, and parse the answer into a `CmusQueryResponse`.
+    /// If cmus is not running, or the socket is not available, this function will return an error.
+    pub fn query(&mut self) -> Result<CmusQueryResponse, CmusError> {
+        let output = match self.send("status") {
+            Ok(output) => output,
+            Err(_) => {
+                // The connection may be stale (e.g. cmus restarted), so try again with a fresh one.
+                #[cfg(feature = "debug")]
+                info!("Lost the connection to cmus, reconnecting...");
+                self.connection = None;
+                self.send("status")?
+            }
+        };
+
+        CmusQueryResponse::from_str(&output).map_err(CmusError::UnknownError)
+    }
+
+    /// Send a command to cmus, and collect its answer.
+    /// The answer is terminated by an empty line.
+    fn send(&mut self, command: &str) -> Result<String, CmusError> {
+        if self.connection.is_none() {
+            self.connection = Some(self.connect()?);
+        }
+        let Some(connection) = self.connection.as_mut() else {
+            unreachable!("The connection is established above");
+        };
+
+        #[cfg(feature = "debug")]
+        debug!("Sending \"{command}\" to cmus.");
+        let result = (|| {
+            let stream = connection.get_mut();
+            stream.write_all(format!("{command}\n").as_bytes())?;
+            stream.flush()?;
+
+            let mut output = String::new();
+            loop {
+                let mut line = String::new();
+                if connection.read_line(&mut line)? == 0 {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+                }
+                if line == "\n" {
+                    break Ok(output);
+                }
+                output.push_str(&line);
+            }
+        })();
+
+        result.map_err(|e| {
+            self.connection = None;
+            CmusError::CmusRunningError(e.to_string())
+        })
+    }
+
+    fn connect(&self) -> Result<BufReader<Box<dyn Stream>>, CmusError> {
+        #[cfg(feature = "debug")]
+        info!("Connecting to cmus at {:?}.", self.address);
+        let to_error = |e: std::io::Error| CmusError::CmusRunningError(e.to_string());
+
+        let stream: Box<dyn Stream> = match &self.address {
+            CmusAddress::Unix(path) => {
+                let stream = UnixStream::connect(path).map_err(to_error)?;
+                stream
+                    .set_read_timeout(Some(IO_TIMEOUT))
+                    .map_err(to_error)?;
+                stream
+                    .set_write_timeout(Some(IO_TIMEOUT))
+                    .map_err(to_error)?;
+                Box::new(stream)
+            }
+            CmusAddress::Tcp(address) => {
+                let mut stream = TcpStream::connect(address).map_err(to_error)?;
+                stream
+                    .set_read_timeout(Some(IO_TIMEOUT))
+                    .map_err(to_error)?;
+                stream
+                    .set_write_timeout(Some(IO_TIMEOUT))
+                    .map_err(to_error)?;
+                // cmus refuses the TCP connections that don't start with the password.
+                let Some(password) = &self.password else {
+                    return Err(CmusError::CmusRunningError(
+                        "A password is required to connect to cmus over TCP".to_string(),
+                    ));
+                };
+                stream
+                    .write_all(format!("passwd {password}\n").as_bytes())
+                    .map_err(to_error)?;
+                Box::new(stream)
+            }
+        };
+
+        Ok(BufReader::new(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    const OUTPUT: &str = include_str!("../../tests/samples/row/cmus-remote-output-row.txt");
+
+    /// Answer the `status` commands like cmus does, until the client disconnects.
+    fn serve(stream: impl Read + Write) -> Vec<String> {
+        let mut stream = BufReader::new(stream);
+        let mut commands = Vec::new();
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).unwrap() == 0 {
+                break commands;
+            }
+            let command = line.trim_end().to_string();
+            if command == "status" {
+                stream
+                    .get_mut()
+                    .write_all(format!("{}\n\n", OUTPUT.trim_end()).as_bytes())
+                    .unwrap();
+            }
+            commands.push(command);
+        }
+    }
+
+    #[test]
+    fn test_parse_the_socket_address() {
+        assert_eq!(
+            CmusAddress::from_str("/tmp/cmus-socket"),
+            Ok(CmusAddress::Unix(PathBuf::from("/tmp/cmus-socket")))
+        );
+        assert_eq!(
+            CmusAddress::from_str("localhost:4000"),
+            Ok(CmusAddress::Tcp("localhost:4000".to_string()))
+        );
+        assert_eq!(
+            CmusAddress::from_str("localhost"),
+            Ok(CmusAddress::Tcp("localhost:3000".to_string()))
+        );
+        assert!(CmusAddress::from_str("").is_err());
+    }
+
+    #[test]
+    fn test_query_cmus_over_unix_socket() {
+        let socket = std::env::temp_dir().join(format!("cmus-notify-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = std::thread::spawn(move || serve(listener.accept().unwrap().0));
+
+        let mut client = CmusClient::new(CmusAddress::Unix(socket.clone()), None);
+        let first = client.query();
+        let second = client.query();
+        drop(client);
+
+        assert_eq!(first, Ok(CmusQueryResponse::from_str(OUTPUT).unwrap()));
+        assert_eq!(first, second);
+        // The both queries should go through the same connection.
+        assert_eq!(server.join().unwrap(), vec!["status", "status"]);
+        let _ = std::fs::remove_file(socket);
+    }
+
+    #[test]
+    fn test_query_cmus_over_tcp_sends_the_password_first() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || serve(listener.accept().unwrap().0));
+
+        let mut client = CmusClient::new(CmusAddress::Tcp(address), Some("secret".to_string()));
+        assert!(client.query().is_ok());
+        drop(client);
+
+        assert_eq!(server.join().unwrap(), vec!["passwd secret", "status"]);
+    }
+
+    #[test]
+    fn test_query_cmus_when_it_is_not_running() {
+        let socket = std::env::temp_dir().join("cmus-notify-test-no-such-socket");
+        let mut client = CmusClient::new(CmusAddress::Unix(socket), None);
+
+        assert!(matches!(
+            client.query(),
+            Err(CmusError::CmusRunningError(_))
+        ));
+    }
+}
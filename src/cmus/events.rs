@@ -1,61 +1,182 @@
-use crate::{CompleteStr, process_template_placeholders};
-use crate::cmus::Track;
+use crate::{CompleteStr, render_template};
+use crate::cmus::{TemplateProcessor, Track};
 use crate::cmus::player_settings::PlayerSettings;
 use crate::notification::Action;
 use crate::settings::Settings;
+use crate::template::Template;
 
+/// A snapshot of the cmus state, the track and the player settings.
+#[derive(PartialEq, Default, Clone)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+pub struct Snapshot {
+    pub track: Track,
+    pub player_settings: PlayerSettings,
+}
+
+impl Snapshot {
+    pub fn new(track: Track, player_settings: PlayerSettings) -> Self {
+        Self {
+            track,
+            player_settings,
+        }
+    }
+}
+
+impl TemplateProcessor for Snapshot {
+    /// Returns the track placeholder value, or the player settings placeholder value.
+    #[inline]
+    fn value(&self, key: &str) -> Option<String> {
+        self.track
+            .value(key)
+            .or_else(|| self.player_settings.value(key))
+    }
+}
+
+/// Every event carries the state before the change, and the state after it, in this order.
+///
+/// See `CmusQueryResponse::events` for the order of the events, and how they're coalesced.
 #[derive(PartialEq)]
 #[cfg_attr(any(feature = "debug", test), derive(Debug))]
 pub enum CmusEvent {
-    StatusChanged(Track, PlayerSettings),
-    TrackChanged(Track, PlayerSettings),
-    VolumeChanged(Track, PlayerSettings),
-    PositionChanged(Track, PlayerSettings),
-    ShuffleChanged(Track, PlayerSettings),
-    RepeatChanged(Track, PlayerSettings),
-    AAAModeChanged(Track, PlayerSettings),
+    StatusChanged(Snapshot, Snapshot),
+    TrackChanged(Snapshot, Snapshot),
+    VolumeChanged(Snapshot, Snapshot),
+    PositionChanged(Snapshot, Snapshot),
+    /// The position jumped forward or backward, further than the time passed since the last query,
+    /// e.g. the user seeked with the keyboard. It's the position before the seek, and the position after it.
+    Seeked(Snapshot, Snapshot),
+    ShuffleChanged(Snapshot, Snapshot),
+    RepeatChanged(Snapshot, Snapshot),
+    RepeatCurrentChanged(Snapshot, Snapshot),
+    AAAModeChanged(Snapshot, Snapshot),
+    /// The song on the internet radio station changed, but the track (the stream URL) is the same.
+    StreamTitleChanged(Snapshot, Snapshot),
+    ContinueChanged(Snapshot, Snapshot),
+    PlayLibraryChanged(Snapshot, Snapshot),
+    PlaySortedChanged(Snapshot, Snapshot),
+    /// The ReplayGain mode, limit or pre-amplification changed.
+    ReplayGainChanged(Snapshot, Snapshot),
+    SoftVolChanged(Snapshot, Snapshot),
+    /// The program connected to cmus, e.g. cmus started.
+    /// The state before it is unknown, so it's the default one.
+    Connected(Snapshot, Snapshot),
+    /// The program lost the connection to cmus, e.g. cmus closed.
+    /// The state before it is the last known one, and the state after it is the default one.
+    Disconnected(Snapshot, Snapshot),
 }
 
 impl CmusEvent {
+    /// Returns the state before the change, and the state after it.
+    pub fn states(&self) -> (&Snapshot, &Snapshot) {
+        use CmusEvent::*;
+        match self {
+            StatusChanged(before, after)
+            | TrackChanged(before, after)
+            | VolumeChanged(before, after)
+            | PositionChanged(before, after)
+            | Seeked(before, after)
+            | ShuffleChanged(before, after)
+            | RepeatChanged(before, after)
+            | RepeatCurrentChanged(before, after)
+            | AAAModeChanged(before, after)
+            | StreamTitleChanged(before, after)
+            | ContinueChanged(before, after)
+            | PlayLibraryChanged(before, after)
+            | PlaySortedChanged(before, after)
+            | ReplayGainChanged(before, after)
+            | SoftVolChanged(before, after)
+            | Connected(before, after)
+            | Disconnected(before, after) => (before, after),
+        }
+    }
+
+    /// Returns the mutable state before the change, and the mutable state after it.
+    pub fn states_mut(&mut self) -> (&mut Snapshot, &mut Snapshot) {
+        use CmusEvent::*;
+        match self {
+            StatusChanged(before, after)
+            | TrackChanged(before, after)
+            | VolumeChanged(before, after)
+            | PositionChanged(before, after)
+            | Seeked(before, after)
+            | ShuffleChanged(before, after)
+            | RepeatChanged(before, after)
+            | RepeatCurrentChanged(before, after)
+            | AAAModeChanged(before, after)
+            | StreamTitleChanged(before, after)
+            | ContinueChanged(before, after)
+            | PlayLibraryChanged(before, after)
+            | PlaySortedChanged(before, after)
+            | ReplayGainChanged(before, after)
+            | SoftVolChanged(before, after)
+            | Connected(before, after)
+            | Disconnected(before, after) => (before, after),
+        }
+    }
+
     pub fn build_notification(
         &self,
         settings: &Settings,
     ) -> Action {
         use CmusEvent::*;
-        let (body_template, summary_template, timeout, track, player_settings) = match self {
-            StatusChanged(track, player_settings) =>
-                (settings.status_notification_body(), settings.status_notification_summary(), settings.status_notification_timeout(), track, player_settings),
-            TrackChanged(track, player_settings) =>
-                (settings.body(), settings.summary(), settings.timeout(), track, player_settings),
-            VolumeChanged(track, player_settings) if settings.show_player_notifications =>
-                (settings.volume_notification_body(), settings.volume_notification_summary(), settings.volume_notification_timeout(), track, player_settings),
-            ShuffleChanged(track, player_settings) if settings.show_player_notifications =>
-                (settings.shuffle_notification_body(), settings.shuffle_notification_summary(), settings.shuffle_notification_timeout(), track, player_settings),
-            RepeatChanged(track, player_settings) if settings.show_player_notifications =>
-                (settings.repeat_notification_body(), settings.repeat_notification_summary(), settings.repeat_notification_timeout(), track, player_settings),
-            AAAModeChanged(track, player_settings) if settings.show_player_notifications =>
-                (settings.aaa_mode_notification_body(), settings.aaa_mode_notification_summary(), settings.aaa_mode_notification_timeout(), track, player_settings),
+        let (before, after) = self.states();
+        let (body_key, summary_key, timeout) = match self {
+            StatusChanged(..) =>
+                ("status_notification_body", "status_notification_summary", settings.status_notification_timeout()),
+            TrackChanged(..) | StreamTitleChanged(..) if after.track.is_stream() =>
+                ("stream_notification_body", "stream_notification_summary", settings.stream_notification_timeout()),
+            TrackChanged(..) =>
+                ("body", "summary", settings.timeout()),
+            VolumeChanged(..) if settings.show_player_notifications =>
+                ("volume_notification_body", "volume_notification_summary", settings.volume_notification_timeout()),
+            Seeked(..) if settings.show_player_notifications =>
+                ("seek_notification_body", "seek_notification_summary", settings.seek_notification_timeout()),
+            ShuffleChanged(..) if settings.show_player_notifications =>
+                ("shuffle_notification_body", "shuffle_notification_summary", settings.shuffle_notification_timeout()),
+            RepeatChanged(..) if settings.show_player_notifications =>
+                ("repeat_notification_body", "repeat_notification_summary", settings.repeat_notification_timeout()),
+            RepeatCurrentChanged(..) if settings.show_player_notifications =>
+                ("repeat_current_notification_body", "repeat_current_notification_summary", settings.repeat_current_notification_timeout()),
+            AAAModeChanged(..) if settings.show_player_notifications =>
+                ("aaa_mode_notification_body", "aaa_mode_notification_summary", settings.aaa_mode_notification_timeout()),
+            ContinueChanged(..) if settings.show_player_notifications =>
+                ("continue_notification_body", "continue_notification_summary", settings.continue_notification_timeout()),
+            PlayLibraryChanged(..) if settings.show_player_notifications =>
+                ("play_library_notification_body", "play_library_notification_summary", settings.play_library_notification_timeout()),
+            PlaySortedChanged(..) if settings.show_player_notifications =>
+                ("play_sorted_notification_body", "play_sorted_notification_summary", settings.play_sorted_notification_timeout()),
+            ReplayGainChanged(..) if settings.show_player_notifications =>
+                ("replaygain_notification_body", "replaygain_notification_summary", settings.replaygain_notification_timeout()),
+            SoftVolChanged(..) if settings.show_player_notifications =>
+                ("softvol_notification_body", "softvol_notification_summary", settings.softvol_notification_timeout()),
+            Connected(..) =>
+                ("connected_notification_body", "connected_notification_summary", settings.connected_notification_timeout()),
+            Disconnected(..) =>
+                ("disconnected_notification_body", "disconnected_notification_summary", settings.disconnected_notification_timeout()),
             _ => { return Action::None },
         };
 
+        let body_template = settings.template(body_key);
+        // The user disabled the notification.
+        if body_template.is_empty() {
+            return Action::None;
+        }
+        let summary_template = settings.template(summary_key);
         let persistent = is_mutable(&body_template) || is_mutable(&summary_template);
 
+        let mut body = String::new();
+        render_template(&body_template, before, after, settings, true, &mut body);
+        let mut summary = String::new();
+        render_template(&summary_template, before, after, settings, false, &mut summary);
+
         Action::Show {
             body: CompleteStr {
-                template: body_template.clone(),
-                str: process_template_placeholders(
-                    body_template,
-                    track,
-                    player_settings,
-                ),
+                template: body_template,
+                str: body,
             },
             summary: CompleteStr {
-                template: summary_template.clone(),
-                str: process_template_placeholders(
-                    summary_template,
-                    track,
-                    player_settings,
-                ),
+                template: summary_template,
+                str: summary,
             },
             timeout: if persistent { 0 } else { timeout * 1000 },
             save: persistent,
@@ -63,21 +184,77 @@ impl CmusEvent {
     }
 }
 
-fn is_mutable(template: &str) -> bool {
-        let mut key = String::new(); // Just a buffer to build the key.
-
-        for c in template.chars() {
-            if c == '{' {
-                key = String::new();
-            } else if c == '}' {
-                match key.as_str() {
-                    "lyrics" | "progress" | "progress_bar" => return true,
-                    _ => {}
-                }
-            } else {
-                key.push(c);
-            }
+/// Returns true if the template shows a value that changes while the track is playing,
+/// so its notification stays visible, and it's refreshed on every position change.
+/// The "prev_" placeholders don't count, they always show the state before the event.
+fn is_mutable(template: &Template) -> bool {
+    template.keys().iter().any(|key| {
+        matches!(
+            *key,
+            "lyrics" | "progress" | "progress_bar" | "position" | "remaining" | "percent"
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::str::FromStr;
+
+    fn snapshot(position: u32) -> Snapshot {
+        let mut track = Track::from_str(include_str!(
+            "../../tests/samples/cmus-remote-output-with-all-tags.txt"
+        ))
+        .unwrap();
+        track.position = position;
+        Snapshot::new(track, PlayerSettings::default())
+    }
+
+    #[test]
+    fn test_refresh_the_notifications_with_the_time_placeholders() {
+        for (body, live) in [
+            ("{title} {position}", true),
+            ("{remaining} left", true),
+            ("{percent}%", true),
+            ("{title} ({duration})", false),
+            ("Skipped at {prev_position}", false),
+        ] {
+            let settings = Settings::parse_from(["cmus-notify", body]);
+            let event = CmusEvent::TrackChanged(snapshot(10), snapshot(0));
+
+            let Action::Show { timeout, save, .. } = event.build_notification(&settings) else {
+                panic!("The notification is not shown for {body:?}");
+            };
+            assert_eq!(save, live, "{body:?}");
+            assert_eq!(timeout == 0, live, "{body:?}");
         }
+    }
 
-    false
+    #[test]
+    fn test_dont_show_the_notifications_with_an_empty_body() {
+        let settings = Settings::parse_from([
+            "cmus-notify",
+            "",
+            "--show-player-notifications",
+            "--repeat-current-notification-body",
+            "",
+            "--continue-notification-body",
+            "",
+        ]);
+        let mut repeat_current = snapshot(10);
+        repeat_current.player_settings.repeat_current = true;
+
+        for event in [
+            CmusEvent::TrackChanged(snapshot(10), snapshot(0)),
+            CmusEvent::RepeatCurrentChanged(snapshot(10), repeat_current.clone()),
+            CmusEvent::ContinueChanged(snapshot(10), repeat_current),
+        ] {
+            assert!(matches!(event.build_notification(&settings), Action::None), "{event:?}");
+        }
+        assert!(matches!(
+            CmusEvent::Seeked(snapshot(10), snapshot(60)).build_notification(&settings),
+            Action::Show { .. }
+        ));
+    }
 }
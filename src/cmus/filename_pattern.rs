@@ -0,0 +1,116 @@
+use regex::Regex;
+use std::str::FromStr;
+
+/// A pattern to extract the tags from the track path, when the track is not tagged,
+/// e.g. "{tracknumber} - {artist} - {title}", or with the directories "{artist}/{album}/{tracknumber} - {title}".
+///
+/// The pattern is matched against the end of the path, without the extension,
+/// and every placeholder matches a part of a single path component.
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+pub struct FilenamePattern {
+    regex: Regex,
+    keys: Vec<String>,
+}
+
+impl FromStr for FilenamePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut regex = String::from("(?:^|/)");
+        let mut keys = Vec::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("Unclosed placeholder in the pattern \"{s}\""))?;
+            let key = rest[start + 1..end].trim();
+            if key.contains('{') {
+                return Err(format!("Unclosed placeholder in the pattern \"{s}\""));
+            }
+            if key.is_empty() || keys.iter().any(|k| k == key) {
+                return Err(format!("Empty or repeated placeholder in the pattern \"{s}\""));
+            }
+
+            regex.push_str(&regex::escape(&rest[..start]));
+            regex.push_str("([^/]+?)");
+            keys.push(key.to_string());
+            rest = &rest[end + 1..];
+        }
+        regex.push_str(&regex::escape(rest));
+        regex.push('$');
+
+        Ok(Self {
+            regex: Regex::new(&regex).map_err(|e| e.to_string())?,
+            keys,
+        })
+    }
+}
+
+impl FilenamePattern {
+    /// Returns the keys of the placeholders in the pattern.
+    #[inline(always)]
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Match the pattern against the track path, and return the tags it found, if it matches.
+    pub fn captures<'a>(&'a self, path: &'a str) -> Option<Vec<(&'a str, &'a str)>> {
+        // Remove the extension, but only from the file name.
+        let path = match path.rsplit_once('.') {
+            Some((stem, ext)) if !ext.contains('/') => stem,
+            _ => path,
+        };
+        let captures = self.regex.captures(path)?;
+
+        Some(
+            self.keys
+                .iter()
+                .zip(captures.iter().skip(1))
+                .filter_map(|(key, value)| Some((key.as_str(), value?.as_str().trim())))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_the_tags_from_the_file_name() {
+        let pattern = FilenamePattern::from_str("{tracknumber} - {artist} - {title}").unwrap();
+
+        assert_eq!(
+            pattern.captures("/Music/08 - The Killers - Mr. Brightside.mp3"),
+            Some(vec![
+                ("tracknumber", "08"),
+                ("artist", "The Killers"),
+                ("title", "Mr. Brightside")
+            ])
+        );
+        assert_eq!(pattern.captures("/Music/Mr. Brightside.mp3"), None);
+    }
+
+    #[test]
+    fn test_extract_the_tags_from_the_directories() {
+        let pattern = FilenamePattern::from_str("{artist}/{album}/{tracknumber} - {title}").unwrap();
+
+        assert_eq!(
+            pattern.captures("/mnt/Music/Owl City/Cinematic/02 - Fiji Water.flac"),
+            Some(vec![
+                ("artist", "Owl City"),
+                ("album", "Cinematic"),
+                ("tracknumber", "02"),
+                ("title", "Fiji Water")
+            ])
+        );
+    }
+
+    #[test]
+    fn test_reject_the_invalid_patterns() {
+        assert!(FilenamePattern::from_str("{artist - {title}").is_err());
+        assert!(FilenamePattern::from_str("{title} - {title}").is_err());
+    }
+}
@@ -1,43 +1,45 @@
+pub mod client;
 pub mod events;
+pub mod filename_pattern;
 pub mod player_settings;
 pub mod query;
 
+use crate::cmus::client::{CmusAddress, CmusClient};
+use crate::cmus::events::{CmusEvent, Snapshot};
+use crate::cmus::filename_pattern::FilenamePattern;
 use crate::cmus::query::CmusQueryResponse;
+use crate::template::Template;
 #[cfg(feature = "debug")]
 use log::{debug, info};
 use parse_display::Display;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::num::ParseIntError;
+use std::path::Path;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use typed_builder::TypedBuilder;
 
+/// Provides the values of the template placeholders.
+/// See the `template` module for the template syntax.
 pub trait TemplateProcessor {
-    fn process(&self, template: String) -> String;
-
-    /// Returns a vector of keys found in the template.
-    /// The keys are the strings between curly braces.
-    fn get_keys(template: &str) -> Vec<String> {
-        let mut keys = Vec::new(); // Just a buffer to store the keys.
-        let mut key = String::new(); // Just a buffer to build the key.
-
-        for c in template.chars() {
-            if c == '{' {
-                key = String::new();
-            } else if c == '}' {
+    /// Returns the value of the placeholder key, or `None` if it's unknown or not available.
+    fn value(&self, key: &str) -> Option<String>;
+
+    /// Render the template with the values of this processor.
+    /// If the template is malformed, it will be returned as it is.
+    fn process(&self, template: String) -> String {
+        #[cfg(feature = "debug")]
+        debug!("Processing the template: {template}");
+        match Template::from_str(&template) {
+            Ok(parsed) => parsed.render(self),
+            Err(_e) => {
                 #[cfg(feature = "debug")]
-                debug!("Found key: {}", key);
-                keys.push(key.clone());
-            } else {
-                key.push(c);
+                debug!("Malformed template {template:?}: {_e}");
+                template
             }
         }
-
-        #[cfg(feature = "debug")]
-        debug!("Found keys: {:?}", keys);
-
-        keys
     }
 }
 
@@ -64,6 +66,9 @@ pub struct Track {
     pub metadata: TrackMetadata,
     pub duration: u32,
     pub position: u32,
+    /// The title of the current song, if the track is an internet radio stream.
+    #[builder(default)]
+    pub stream: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Error)]
@@ -86,42 +91,25 @@ pub enum CmusError {
     UnknownAAAMode(String),
     #[error("Unknown shuffle mode: {0}")]
     UnknownShuffleMode(String),
+    #[error("Unknown ReplayGain mode: {0}")]
+    UnknownReplayGainMode(String),
     #[error("No events")]
     NoEvents,
 }
 
 impl TemplateProcessor for Track {
-    /// Process the template with the track metadata.
-    /// The template is a string with placeholders that will be replaced with the track metadata.
-    /// The unknown placeholders will be skipped (don't replaced with anything, because they are maybe placeholders for player settings).
+    /// Returns the track placeholder value, e.g. "{title}", "{status}", or any of the track tags, e.g. "{album}".
     #[inline]
-    fn process(&self, template: String) -> String {
-        #[cfg(feature = "debug")]
-        {
-            info!("Processing the template placeholders.");
-            debug!("Template: {template}");
-            debug!("Track: {self:?}");
+    fn value(&self, key: &str) -> Option<String> {
+        match key {
+            "status" => Some(self.status.to_string()),
+            "title" => Some(self.get_name().to_string()),
+            "progress" | "position" | "duration" | "remaining" | "percent" => self.time_value(key, None),
+            "filename" | "stem" | "dirname" | "parent_dir" | "ext" | "format" => self.file_value(key),
+            "stream" => self.stream.clone(),
+            "station" => Some(self.get_station().to_string()),
+            _ => self.metadata.get(key).map(|r| r.to_string()),
         }
-        let mut processed = template.to_string();
-
-        Self::get_keys(template.as_str()).iter().for_each(|key| {
-            #[cfg(feature = "debug")]
-            debug!("Replacing the placeholder {{{key}}} with its matching value.");
-            // Replace the key with their matching value if exists
-            if let Some(value) = match key.as_str() {
-                "status" => Some(self.status.to_string()),
-                "title" => Some(self.get_name().to_string()),
-                "progress" => Some(format!("{:.2}/{:.2}", self.duration as f32 / 60.0, self.position as f32 / 60.0)),
-                _ => self.metadata.get(key).map(|r| r.to_string()),
-            } {
-                processed = processed.replace(&format!("{{{key}}}"), value.as_str());
-            }
-        });
-
-        #[cfg(feature = "debug")]
-        debug!("Processed template: {processed}");
-
-        processed
     }
 }
 
@@ -146,6 +134,7 @@ impl FromStr for Track {
     /// Pares the first 4 lines.
     /// The first line is the status, the second is the path, the third is the duration, and the fourth is the position.
     /// The rest of the lines are tags, and the player settings, so we'll send them to `TrackMetadata::parse`, to get the tags.
+    /// And if the track is a stream, the stream title will be in the "stream" line.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         #[cfg(feature = "debug")]
         info!("Parsing track from string: {}", s);
@@ -177,7 +166,9 @@ impl FromStr for Track {
                     .split_once(' ')
                     .ok_or(CmusError::DurationError("Empty duration".to_string()))?
                     .1
-                    .parse()
+                    .parse::<i64>()
+                    // The streams have an unknown duration, which is "-1".
+                    .map(|duration| duration.max(0) as u32)
                     .map_err(|e: ParseIntError| CmusError::DurationError(e.to_string()))?,
             )
             .position(
@@ -190,12 +181,28 @@ impl FromStr for Track {
                     .parse()
                     .map_err(|e: ParseIntError| CmusError::PositionError(e.to_string()))?,
             )
+            .stream(
+                s.lines()
+                    .find_map(|line| line.strip_prefix("stream "))
+                    .map(str::to_string),
+            )
             .metadata(TrackMetadata::parse(lines))
             .build())
     }
 }
 
 impl TrackMetadata {
+    /// The tags cmus knows about, and reports in the `cmus-remote -Q` output.
+    pub const KNOWN_TAGS: &'static [&'static str] = &[
+        "artist", "album", "title", "tracknumber", "discnumber", "date", "originaldate", "genre", "comment",
+        "albumartist", "artistsort", "albumartistsort", "albumsort", "titlesort", "compilation", "media",
+        "label", "publisher", "work", "opus", "partnumber", "part", "subtitle", "composer", "conductor",
+        "lyricist", "performer", "remixer", "arranger", "bpm", "grouping", "isrc", "lyrics",
+        "replaygain_track_gain", "replaygain_track_peak", "replaygain_album_gain", "replaygain_album_peak",
+        "musicbrainz_trackid", "musicbrainz_albumid", "musicbrainz_artistid", "musicbrainz_albumartistid",
+        "musicbrainz_releasegroupid", "musicbrainz_releasetrackid", "musicbrainz_workid",
+    ];
+
     /// Parse the tags from the rest of `cmus-remote -Q` output.
     /// This function will assume you processed the first 4 lines, and remove them from the iterator.
     ///
@@ -218,6 +225,7 @@ impl TrackMetadata {
                     debug!("Inserting tag: {} = {}", key, value);
                     tags.insert(key.to_string(), value.to_string());
                 }
+                Some(("stream", _)) => continue, // The stream title is parsed by `Track::from_str`.
                 _ => break, // We've reached the end of the tags.
             }
         }
@@ -228,25 +236,315 @@ impl TrackMetadata {
     pub fn get(&self, key: &str) -> Option<&str> {
         self.tags.get(key).map(|s| s.as_str())
     }
+
+    /// Returns all the tags, with their keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.tags.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Set the tag, only if it's missing (or empty).
+    pub fn set_if_missing(&mut self, key: &str, value: &str) {
+        if self.get(key).map_or(true, str::is_empty) {
+            self.tags.insert(key.to_string(), value.to_string());
+        }
+    }
 }
 
 impl Track {
+    /// The placeholders of the track, besides its tags.
+    pub const PLACEHOLDERS: &'static [&'static str] = &[
+        "status", "title", "progress", "position", "duration", "remaining", "percent", "stream", "station",
+        "filename", "stem", "dirname", "parent_dir", "ext", "format",
+    ];
+
+    /// Returns the value of the time placeholders, formatted with the given format (see `format_time`):
+    /// "{position}", "{duration}", "{remaining}", "{percent}" (the played percentage),
+    /// and "{progress}" ("position / duration").
+    ///
+    /// If the duration is unknown (e.g. a stream), only the position is available, and "{progress}" is the position.
+    pub fn time_value(&self, key: &str, format: Option<&str>) -> Option<String> {
+        let known_duration = self.duration > 0;
+        match key {
+            "position" => Some(format_time(self.position, format)),
+            "duration" if known_duration => Some(format_time(self.duration, format)),
+            "remaining" if known_duration => Some(format_time(
+                self.duration.saturating_sub(self.position),
+                format,
+            )),
+            "percent" if known_duration => Some(
+                (self.position.min(self.duration) as u64 * 100 / self.duration as u64).to_string(),
+            ),
+            "progress" if known_duration => Some(format!(
+                "{} / {}",
+                format_time(self.position, format),
+                format_time(self.duration, format)
+            )),
+            "progress" => Some(format_time(self.position, format)),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the placeholders derived from the track path:
+    /// "{filename}" (e.g. "08 - Photograph.mp3"), "{stem}" (e.g. "08 - Photograph"),
+    /// "{dirname}" (the full directory path), "{parent_dir}" (the directory name, e.g. the album directory),
+    /// "{ext}" (e.g. "mp3") and "{format}" (e.g. "MP3", "FLAC", "Opus", or the container, "Ogg" or "M4A",
+    /// because the extension doesn't tell which codec is inside).
+    ///
+    /// They are not available for the streams, because the path is a URL.
+    pub fn file_value(&self, key: &str) -> Option<String> {
+        if self.is_stream() {
+            return None;
+        }
+        let path = Path::new(&self.path);
+        let to_string = |s: &std::ffi::OsStr| s.to_string_lossy().into_owned();
+        match key {
+            "filename" => path.file_name().map(to_string),
+            "stem" => path.file_stem().map(to_string),
+            "dirname" => path.parent().map(|dir| to_string(dir.as_os_str())),
+            "parent_dir" => path.parent().and_then(Path::file_name).map(to_string),
+            "ext" => path.extension().map(to_string),
+            "format" => path.extension().map(|ext| {
+                let ext = ext.to_string_lossy().to_lowercase();
+                match ext.as_str() {
+                    "opus" => "Opus".to_string(),
+                    "ogg" | "oga" => "Ogg".to_string(),
+                    "wv" => "WavPack".to_string(),
+                    "mpc" => "Musepack".to_string(),
+                    _ => ext.to_uppercase(),
+                }
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the tags in the track path, with the first filename pattern that matches it.
+    pub fn path_tags(&self, patterns: &[FilenamePattern]) -> Vec<(String, String)> {
+        if self.is_stream() {
+            return Vec::new();
+        }
+        patterns
+            .iter()
+            .find_map(|pattern| pattern.captures(&self.path))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Fill the missing tags with the given ones, the tags that exist are never replaced.
+    pub fn fill_metadata(&mut self, tags: &[(String, String)]) {
+        for (key, value) in tags {
+            self.metadata.set_if_missing(key, value);
+        }
+    }
+
+    /// Fill the missing tags from the track path, with the first filename pattern that matches it.
+    /// The tags that exist are never replaced.
+    pub fn fill_metadata_from_path(&mut self, patterns: &[FilenamePattern]) {
+        let tags = self.path_tags(patterns);
+        self.fill_metadata(&tags);
+    }
+
     /// Returns the name of the track.
     ///
     /// This is the title, if it exists, otherwise it's the file name without the extension.
     pub fn get_name(&self) -> &str {
         self.metadata.get("title").unwrap_or_else(|| {
-            self.path
-                .split('/')
-                .last()
-                .unwrap_or("")
-                .split_once('.')
-                .unwrap_or(("", ""))
-                .0
+            let file_name = self.path.split('/').next_back().unwrap_or("");
+            file_name
+                .rsplit_once('.')
+                .map_or(file_name, |(stem, _)| stem)
         })
     }
 }
 
+impl Track {
+    /// Returns true if the track is an internet radio stream.
+    #[inline]
+    pub fn is_stream(&self) -> bool {
+        self.stream.is_some()
+            || self.path.starts_with("http://")
+            || self.path.starts_with("https://")
+    }
+
+    /// Returns the name of the station, if the track is a stream.
+    ///
+    /// This is the title, if it exists, otherwise it's the stream URL.
+    pub fn get_station(&self) -> &str {
+        self.metadata.get("title").unwrap_or(&self.path)
+    }
+}
+
+/// The way we talk to cmus.
+pub enum CmusConnection {
+    /// Talk to the cmus socket directly, without spawning any process.
+    Native(CmusClient),
+    /// Run the `cmus-remote -Q` command, for the setups that need a custom run command (e.g. flatpak, snap).
+    Remote(std::process::Command),
+}
+
+impl CmusConnection {
+    /// Choose the way we'll talk to cmus.
+    /// If the cmus-remote binary is the default one, we'll talk to the cmus socket directly,
+    /// otherwise (e.g. "flatpak run io.github.cmus.cmus") we'll fall back to run the command.
+    /// This function it should call only one time entire the program life time.
+    pub fn new(
+        cmus_remote_bin: &str,
+        socket_addr: &Option<String>,
+        socket_pass: &Option<String>,
+    ) -> Self {
+        if cmus_remote_bin.trim() == "cmus-remote" {
+            let address = match socket_addr {
+                Some(socket_addr) => CmusAddress::from_str(socket_addr),
+                None => Ok(CmusAddress::default()),
+            };
+            if let Ok(address) = address {
+                #[cfg(feature = "debug")]
+                info!("Using the native cmus client, address: {:?}", address);
+                return Self::Native(CmusClient::new(address, socket_pass.clone()));
+            }
+        }
+
+        #[cfg(feature = "debug")]
+        info!("Using the \"{cmus_remote_bin}\" command to query cmus.");
+        Self::Remote(build_query_command(
+            cmus_remote_bin,
+            socket_addr,
+            socket_pass,
+        ))
+    }
+
+    /// Make a status request to cmus, and parse it into a `CmusQueryResponse`.
+    #[inline]
+    pub fn query(&mut self) -> Result<CmusQueryResponse, CmusError> {
+        match self {
+            Self::Native(client) => client.query(),
+            Self::Remote(query_command) => ping_cmus(query_command),
+        }
+    }
+}
+
+/// Format the time in seconds as "m:ss", or "h:mm:ss" if it's an hour or longer.
+///
+/// Or with the given format, where "%h", "%m" and "%s" are the hours, minutes and seconds,
+/// and "%H", "%M" and "%S" are the same, but padded to two digits, and "%%" is a literal "%".
+/// If the format doesn't have the hours, the minutes are the total minutes, e.g. "%m:%S" → "75:03".
+pub fn format_time(seconds: u32, format: Option<&str>) -> String {
+    let Some(format) = format else {
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        return if hours > 0 {
+            format!("{hours}:{minutes:02}:{seconds:02}")
+        } else {
+            format!("{minutes}:{seconds:02}")
+        };
+    };
+
+    let has_hours = format.contains("%h") || format.contains("%H");
+    let hours = seconds / 3600;
+    let minutes = if has_hours { seconds / 60 % 60 } else { seconds / 60 };
+    let seconds = seconds % 60;
+
+    let mut formatted = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('h') => formatted.push_str(&hours.to_string()),
+            Some('H') => formatted.push_str(&format!("{hours:02}")),
+            Some('m') => formatted.push_str(&minutes.to_string()),
+            Some('M') => formatted.push_str(&format!("{minutes:02}")),
+            Some('s') => formatted.push_str(&seconds.to_string()),
+            Some('S') => formatted.push_str(&format!("{seconds:02}")),
+            Some('%') => formatted.push('%'),
+            Some(other) => {
+                formatted.push('%');
+                formatted.push(other);
+            }
+            None => formatted.push('%'),
+        }
+    }
+    formatted
+}
+
+/// The state of our connection with cmus.
+#[derive(PartialEq, Clone, Copy)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+pub enum ConnectionState {
+    /// We didn't query cmus yet, since the given time.
+    /// The first query sets the state without any event, so we don't notify on every start.
+    Unknown(Instant),
+    Connected,
+    /// cmus is not reachable since the given time.
+    Disconnected(Instant),
+}
+
+impl Default for ConnectionState {
+    fn default() -> Self {
+        Self::Unknown(Instant::now())
+    }
+}
+
+impl ConnectionState {
+    /// Update the state with the result of the last query.
+    /// If the state changed, returns the `Connected` or `Disconnected` event.
+    ///
+    /// * `response` - The response of the last query, or `None` if the query failed.
+    /// * `last_response` - The last known response, to describe the state before the disconnection.
+    pub fn update(
+        &mut self,
+        response: Option<&CmusQueryResponse>,
+        last_response: &CmusQueryResponse,
+    ) -> Option<CmusEvent> {
+        match (*self, response) {
+            (Self::Unknown(_), Some(_)) => {
+                *self = Self::Connected;
+                None
+            }
+            (Self::Unknown(since), None) => {
+                *self = Self::Disconnected(since);
+                None
+            }
+            (Self::Disconnected(_), Some(response)) => {
+                #[cfg(feature = "debug")]
+                info!("Connected to cmus.");
+                *self = Self::Connected;
+                Some(CmusEvent::Connected(
+                    Snapshot::default(),
+                    Snapshot::new(
+                        response.track().unwrap_or_default(),
+                        response.player_settings().unwrap_or_default(),
+                    ),
+                ))
+            }
+            (Self::Connected, None) => {
+                #[cfg(feature = "debug")]
+                info!("Lost the connection to cmus.");
+                *self = Self::Disconnected(Instant::now());
+                Some(CmusEvent::Disconnected(
+                    Snapshot::new(
+                        last_response.track().unwrap_or_default(),
+                        last_response.player_settings().unwrap_or_default(),
+                    ),
+                    Snapshot::default(),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns how long cmus is not reachable, or `None` if we're connected.
+    #[inline(always)]
+    pub fn disconnected_for(&self) -> Option<Duration> {
+        match self {
+            Self::Connected => None,
+            Self::Unknown(since) | Self::Disconnected(since) => Some(since.elapsed()),
+        }
+    }
+}
+
 /// Make a status request to cmus.
 /// And collect the output, and parse it into a `CmusQueryResponse`.
 /// If the cmus is not running, or the socket is not available, this function will return an error.
@@ -382,6 +680,24 @@ mod tests {
         assert_eq!(track.metadata.tags.get("bpm"), Some(&"146".to_string()));
     }
 
+    #[test]
+    fn test_create_stream_track_from_str() {
+        let track = Track::from_str(include_str!(
+            "../../tests/samples/cmus-remote-output-stream.txt"
+        ))
+        .unwrap();
+
+        assert!(track.is_stream());
+        assert_eq!(track.duration, 0);
+        assert_eq!(track.position, 731);
+        assert_eq!(track.stream.as_deref(), Some("Boards of Canada - Roygbiv"));
+        assert_eq!(track.metadata.get("genre"), Some("Ambient"));
+        assert_eq!(
+            track.process("{stream} on {station}".to_string()),
+            "Boards of Canada - Roygbiv on Groove Salad: a nicely chilled plate of ambient beats"
+        );
+    }
+
     #[test]
     fn test_parse_metadata_from_the_string() {
         let metadata = TrackMetadata::parse(SOME_TAGS.lines());
@@ -419,6 +735,138 @@ mod tests {
         assert_eq!(metadata.tags.get("bpm"), Some(&"146".to_string()));
     }
 
+    #[test]
+    fn test_format_the_time() {
+        assert_eq!(format_time(0, None), "0:00");
+        assert_eq!(format_time(83, None), "1:23");
+        assert_eq!(format_time(3725, None), "1:02:05");
+        assert_eq!(format_time(4503, Some("%m:%S")), "75:03");
+        assert_eq!(format_time(4503, Some("%Hh %Mm %Ss (100%%)")), "01h 15m 03s (100%)");
+    }
+
+    #[test]
+    fn test_process_the_time_placeholders() {
+        let track = Track::from_str(include_str!(
+            "../../tests/samples/row/cmus-remote-output-row.txt"
+        ))
+        .unwrap();
+
+        assert_eq!(
+            track.process("{progress} ({remaining} left, {percent}%)".to_string()),
+            "0:34 / 4:02 (3:28 left, 14%)"
+        );
+        assert_eq!(track.time_value("duration", Some("%M:%S")), Some("04:02".to_string()));
+    }
+
+    #[test]
+    fn test_process_the_file_placeholders() {
+        let track = Track::from_str(include_str!(
+            "../../tests/samples/row/cmus-remote-output-row.txt"
+        ))
+        .unwrap();
+
+        assert_eq!(
+            track.process("{filename}|{stem}|{parent_dir}|{ext}|{format}".to_string()),
+            "12 - Haunted.mp3|12 - Haunted|Taylor Swift - Speak Now|mp3|MP3"
+        );
+        assert_eq!(
+            track.file_value("dirname").as_deref(),
+            Some("/mnt/Data/Music/FLAC/Taylor Swift/Taylor Swift - Speak Now")
+        );
+
+        let m4a = Track {
+            path: "/Music/Photograph.m4a".to_string(),
+            ..Track::default()
+        };
+        assert_eq!(m4a.file_value("format").as_deref(), Some("M4A"));
+        let ogg = Track {
+            path: "/Music/Photograph.ogg".to_string(),
+            ..Track::default()
+        };
+        assert_eq!(ogg.file_value("format").as_deref(), Some("Ogg"));
+    }
+
+    #[test]
+    fn test_fill_the_missing_tags_from_the_path() {
+        let mut track = Track::builder()
+            .status(TrackStatus::Playing)
+            .path("/Music/The Killers/08 - Mr. Brightside.mp3".to_string())
+            .metadata(TrackMetadata::default())
+            .duration(222)
+            .position(0)
+            .build();
+        assert_eq!(track.get_name(), "08 - Mr. Brightside");
+
+        track.metadata.set_if_missing("artist", "Killers");
+        track.fill_metadata_from_path(&[
+            FilenamePattern::from_str("{artist}/{tracknumber} - {title}").unwrap(),
+            FilenamePattern::from_str("{artist} - {title}").unwrap(),
+        ]);
+
+        assert_eq!(track.get_name(), "Mr. Brightside");
+        assert_eq!(track.metadata.get("tracknumber"), Some("08"));
+        // The existing tags are kept.
+        assert_eq!(track.metadata.get("artist"), Some("Killers"));
+    }
+
+    #[test]
+    fn test_connection_state_emits_the_transition_events_once() {
+        let response = CmusQueryResponse::from_str(include_str!(
+            "../../tests/samples/row/cmus-remote-output-row.txt"
+        ))
+        .unwrap();
+        let mut state = ConnectionState::Disconnected(Instant::now());
+
+        assert!(matches!(
+            state.update(Some(&response), &CmusQueryResponse::default()),
+            Some(CmusEvent::Connected(_, after)) if after.track.get_name() == "Haunted"
+        ));
+        assert_eq!(state.update(Some(&response), &response), None);
+        assert_eq!(state.disconnected_for(), None);
+
+        assert!(matches!(
+            state.update(None, &response),
+            Some(CmusEvent::Disconnected(before, _)) if before.track.get_name() == "Haunted"
+        ));
+        assert_eq!(state.update(None, &response), None);
+        assert!(state.disconnected_for().is_some());
+    }
+
+    #[test]
+    fn test_connection_state_is_silent_on_the_first_query() {
+        let response = CmusQueryResponse::from_str(include_str!(
+            "../../tests/samples/row/cmus-remote-output-row.txt"
+        ))
+        .unwrap();
+
+        let mut state = ConnectionState::default();
+        assert_eq!(state.update(Some(&response), &CmusQueryResponse::default()), None);
+        assert_eq!(state.disconnected_for(), None);
+
+        // cmus wasn't running when we started, so it's a real start when it's reachable.
+        let mut state = ConnectionState::default();
+        assert_eq!(state.update(None, &CmusQueryResponse::default()), None);
+        assert!(state.disconnected_for().is_some());
+        assert!(matches!(
+            state.update(Some(&response), &CmusQueryResponse::default()),
+            Some(CmusEvent::Connected(..))
+        ));
+    }
+
+    #[test]
+    fn test_use_the_native_client_with_the_default_remote_bin() {
+        let connection = CmusConnection::new("cmus-remote", &None, &None);
+
+        assert!(matches!(connection, CmusConnection::Native(_)));
+    }
+
+    #[test]
+    fn test_fall_back_to_the_remote_command_with_a_custom_bin_path() {
+        let connection = CmusConnection::new("flatpak run io.github.cmus.cmus", &None, &None);
+
+        assert!(matches!(connection, CmusConnection::Remote(_)));
+    }
+
     #[test]
     fn test_build_the_query_command_with_no_custom_socket_and_no_pass() {
         let command = build_query_command("cmus-remote", &None, &None);
@@ -2,21 +2,30 @@ use crate::cmus::{CmusError, TemplateProcessor};
 #[cfg(feature = "debug")]
 use log::{debug, info};
 use parse_display::Display;
-use std::num::ParseIntError;
+use std::num::{ParseFloatError, ParseIntError};
 use std::str::FromStr;
 
-#[derive(PartialEq, Clone)]
-#[cfg_attr(feature = "debug", derive(Debug))]
+#[derive(PartialEq, Default, Clone)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
 pub struct PlayerSettings {
     pub repeat: bool,
     pub repeat_current: bool,
     pub shuffle: Shuffle,
     pub aaa_mode: AAAMode,
     pub volume: Volume,
+    /// Play the next track when the current one ends (the cmus "continue" option).
+    pub continue_playing: bool,
+    pub play_library: bool,
+    pub play_sorted: bool,
+    pub replaygain: ReplayGain,
+    pub replaygain_limit: bool,
+    /// The ReplayGain pre-amplification, in dB.
+    pub replaygain_preamp: f32,
+    pub softvol: bool,
 }
 
 #[derive(Display, PartialEq, Default, Clone)]
-#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
 pub enum Shuffle {
     #[default]
     Off,
@@ -25,14 +34,14 @@ pub enum Shuffle {
 }
 
 #[derive(PartialEq, Default, Clone)]
-#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
 pub struct Volume {
     pub left: u8,
     pub right: u8,
 }
 
 #[derive(Display, PartialEq, Default, Clone)]
-#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
 pub enum AAAMode {
     #[default]
     All,
@@ -40,43 +49,55 @@ pub enum AAAMode {
     Artist,
 }
 
-impl TemplateProcessor for PlayerSettings {
-    /// Replace all keys in the template with the corresponding values.
-    /// If the key is unknown, it will be replaced with an empty string.
-    /// This function should be used after the track metadata placeholders have been replaced.
-    #[inline(always)]
-    fn process(&self, template: String) -> String {
-        #[cfg(feature = "debug")]
-        {
-            info!("Processing template: {}", template);
-            debug!("Processing template with player settings: {:?}", self);
-        }
-        let mut processed = template.clone();
-
-        Self::get_keys(template.as_str()).iter().for_each(|key| {
-            let value = match key.as_str() {
-                "repeat" => self.repeat.to_string(),
-                "repeat_current" => self.repeat_current.to_string(),
-                "shuffle" => self.shuffle.to_string(),
-                "aaa_mode" => self.aaa_mode.to_string(),
-                "volume_left" => self.volume.left.to_string(),
-                "volume_right" => self.volume.right.to_string(),
-                "volume" => {
-                    if self.volume.left == self.volume.right {
-                        self.volume.left.to_string()
-                    } else {
-                        format!("{}:{}", self.volume.left, self.volume.right)
-                    }
-                }
-                _ => "".to_string(),
-            };
-            processed = processed.replace(&format!("{{{key}}}"), &value);
-        });
+#[derive(Display, PartialEq, Default, Clone)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+#[display(style = "kebab-case")]
+pub enum ReplayGain {
+    #[default]
+    Disabled,
+    Track,
+    Album,
+    TrackPreferred,
+    AlbumPreferred,
+    Smart,
+}
 
-        #[cfg(feature = "debug")]
-        info!("Processed template: {}", processed);
+impl PlayerSettings {
+    /// The placeholders of the player settings.
+    pub const PLACEHOLDERS: &'static [&'static str] = &[
+        "repeat", "repeat_current", "shuffle", "aaa_mode", "continue", "play_library", "play_sorted",
+        "replaygain", "replaygain_limit", "replaygain_preamp", "softvol", "volume", "volume_left", "volume_right",
+    ];
+}
 
-        processed
+impl TemplateProcessor for PlayerSettings {
+    /// Returns the player settings placeholder value, e.g. "{volume}", "{shuffle}".
+    #[inline]
+    fn value(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "repeat" => self.repeat.to_string(),
+            "repeat_current" => self.repeat_current.to_string(),
+            "shuffle" => self.shuffle.to_string(),
+            "aaa_mode" => self.aaa_mode.to_string(),
+            "continue" => self.continue_playing.to_string(),
+            "play_library" => self.play_library.to_string(),
+            "play_sorted" => self.play_sorted.to_string(),
+            "replaygain" => self.replaygain.to_string(),
+            "replaygain_limit" => self.replaygain_limit.to_string(),
+            "replaygain_preamp" => format!("{:.1}", self.replaygain_preamp),
+            "softvol" => self.softvol.to_string(),
+            "volume_left" => self.volume.left.to_string(),
+            "volume_right" => self.volume.right.to_string(),
+            "volume" => {
+                if self.volume.left == self.volume.right {
+                    self.volume.left.to_string()
+                } else {
+                    format!("{}:{}", self.volume.left, self.volume.right)
+                }
+            }
+            _ => return None,
+        };
+        Some(value)
     }
 }
 
@@ -106,54 +127,70 @@ impl FromStr for Shuffle {
     }
 }
 
+impl FromStr for ReplayGain {
+    type Err = CmusError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disabled" => Ok(Self::Disabled),
+            "track" => Ok(Self::Track),
+            "album" => Ok(Self::Album),
+            "track-preferred" => Ok(Self::TrackPreferred),
+            "album-preferred" => Ok(Self::AlbumPreferred),
+            "smart" => Ok(Self::Smart),
+            _ => Err(CmusError::UnknownReplayGainMode(s.to_string())),
+        }
+    }
+}
+
 impl FromStr for PlayerSettings {
     type Err = CmusError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         #[cfg(feature = "debug")]
         info!("Parsing cmus response from string: {}", s);
-        let mut repeat = false;
-        let mut repeat_current = false;
-        let mut shuffle = Shuffle::default();
-        let mut aaa_mode = AAAMode::default();
-        let mut volume = Volume::default();
+        let mut settings = Self::default();
 
         for line in s.lines() {
             #[cfg(feature = "debug")]
             debug!("Parsing line: {}", line);
-            if line.starts_with("set ") {
-                let line = &line[4..];
+            if let Some(line) = line.strip_prefix("set ") {
                 let (key, value) = line.split_once(' ').ok_or(CmusError::UnknownError(
                     "Corrupted cmus response".to_string(),
                 ))?;
 
                 match key {
-                    "repeat" => repeat = value == "true",
-                    "repeat_current" => repeat_current = value == "true",
-                    "shuffle" => shuffle = Shuffle::from_str(value)?,
-                    "aaa_mode" => aaa_mode = AAAMode::from_str(value)?,
+                    "repeat" => settings.repeat = value == "true",
+                    "repeat_current" => settings.repeat_current = value == "true",
+                    "shuffle" => settings.shuffle = Shuffle::from_str(value)?,
+                    "aaa_mode" => settings.aaa_mode = AAAMode::from_str(value)?,
                     "vol_left" => {
-                        volume.left = value
+                        settings.volume.left = value
                             .parse()
                             .map_err(|e: ParseIntError| CmusError::UnknownError(e.to_string()))?
                     }
                     "vol_right" => {
-                        volume.right = value
+                        settings.volume.right = value
                             .parse()
                             .map_err(|e: ParseIntError| CmusError::UnknownError(e.to_string()))?
                     }
+                    "continue" => settings.continue_playing = value == "true",
+                    "play_library" => settings.play_library = value == "true",
+                    "play_sorted" => settings.play_sorted = value == "true",
+                    "replaygain" => settings.replaygain = ReplayGain::from_str(value)?,
+                    "replaygain_limit" => settings.replaygain_limit = value == "true",
+                    "replaygain_preamp" => {
+                        settings.replaygain_preamp = value
+                            .parse()
+                            .map_err(|e: ParseFloatError| CmusError::UnknownError(e.to_string()))?
+                    }
+                    "softvol" => settings.softvol = value == "true",
                     _ => {}
                 }
             }
         }
 
-        Ok(Self {
-            repeat,
-            repeat_current,
-            shuffle,
-            aaa_mode,
-            volume,
-        })
+        Ok(settings)
     }
 }
 
@@ -211,7 +248,46 @@ mod tests {
                     left: 46,
                     right: 46,
                 },
+                continue_playing: true,
+                play_library: true,
+                play_sorted: true,
+                replaygain: ReplayGain::Disabled,
+                replaygain_limit: true,
+                replaygain_preamp: 0.0,
+                softvol: false,
             })
         );
     }
+
+    #[test]
+    fn test_parse_replaygain_mode_from_str() {
+        assert_eq!(ReplayGain::from_str("disabled"), Ok(ReplayGain::Disabled));
+        assert_eq!(ReplayGain::from_str("album"), Ok(ReplayGain::Album));
+        assert_eq!(
+            ReplayGain::from_str("track-preferred"),
+            Ok(ReplayGain::TrackPreferred)
+        );
+        assert_eq!(
+            ReplayGain::from_str("unknown"),
+            Err(CmusError::UnknownReplayGainMode("unknown".to_string()))
+        );
+        assert_eq!(ReplayGain::AlbumPreferred.to_string(), "album-preferred");
+    }
+
+    #[test]
+    fn test_process_the_player_settings_placeholders() {
+        let settings = PlayerSettings {
+            continue_playing: true,
+            replaygain: ReplayGain::Smart,
+            replaygain_preamp: -2.5,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            settings.process(
+                "{continue} {play_library} {replaygain} {replaygain_preamp} {softvol}".to_string()
+            ),
+            "true false smart -2.5 false"
+        );
+    }
 }
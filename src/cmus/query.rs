@@ -1,15 +1,20 @@
-use crate::cmus::events::CmusEvent;
+use crate::cmus::events::{CmusEvent, Snapshot};
 use crate::cmus::player_settings::PlayerSettings;
-use crate::cmus::{CmusError, Track};
+use crate::cmus::{CmusError, Track, TrackStatus};
 #[cfg(feature = "debug")]
 use log::{debug, info};
 use std::str::FromStr;
+use std::time::Duration;
+
+/// How far (in seconds) the position can drift from the expected one, before we consider it a seek.
+/// cmus reports the position in whole seconds, and the queries don't happen exactly on time.
+const SEEK_TOLERANCE: u64 = 2;
 
 /// This struct is used to store the row status response from cmus.
 /// So we don't parse it and take the time then we don't need it.
 /// We only parse it when we need it.
 #[derive(PartialEq, Default)]
-#[cfg_attr(feature = "debug", derive(Debug))]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
 pub struct CmusQueryResponse {
     track_row: String,
     player_settings_row: String,
@@ -33,6 +38,22 @@ impl FromStr for CmusQueryResponse {
 }
 
 impl CmusQueryResponse {
+    /// Build a response from a track row, and reuse the player settings of another response.
+    /// This is useful when we know the track info only, e.g. from the status display program arguments.
+    #[inline]
+    pub fn with_player_settings_of(track_row: String, other: &Self) -> Self {
+        Self {
+            track_row,
+            player_settings_row: other.player_settings_row.clone(),
+        }
+    }
+
+    /// Returns true if cmus is playing, without parsing the track info.
+    #[inline(always)]
+    pub fn is_playing(&self) -> bool {
+        self.track_row.lines().next() == Some("status playing")
+    }
+
     /// Actually process and parse the track info, from the cmus response.
     #[inline(always)]
     pub fn track(&self) -> Result<Track, CmusError> {
@@ -45,8 +66,22 @@ impl CmusQueryResponse {
         PlayerSettings::from_str(&self.player_settings_row)
     }
 
-    /// Compare this response with another one, and return the events that happened.
-    pub fn events(&self, other: &Self) -> Result<Vec<CmusEvent>, CmusError> {
+    /// Compare this response (the previous one) with another one (the new one), and return the events that happened.
+    /// Every event carries the state before the change, and the state after it.
+    ///
+    /// Every changed dimension produces its own event, always in this order:
+    /// 1. `TrackChanged`, if the track path changed.
+    /// 2. `StreamTitleChanged`, `StatusChanged`, `PositionChanged`, if the stream title, the status, or the position changed.
+    ///    And `Seeked` right after `PositionChanged`, if the position doesn't match the played time.
+    /// 3. One event for every changed player setting, in this order: shuffle, repeat, repeat current, AAA mode,
+    ///    volume, continue, play library, play sorted, ReplayGain (mode, limit or pre-amplification), soft volume.
+    ///
+    /// The coalescing policy: a `TrackChanged` event replaces the stream title, status and position events,
+    /// because they describe the new track, not a change of the old one, and the new state is in the event anyway.
+    /// The player settings changes are always reported, even with a `TrackChanged` event.
+    ///
+    /// * `played` - How long cmus was playing since this (the previous) response, to tell the seeks from the normal playback.
+    pub fn events(&self, other: &Self, played: Duration) -> Result<Vec<CmusEvent>, CmusError> {
         #[cfg(feature = "debug")]
         info!("Comparing cmus responses: {:?} and {:?}", self, other);
 
@@ -58,103 +93,139 @@ impl CmusQueryResponse {
 
         let mut events = Vec::new();
 
-        let track = self.track()?;
-        let other_track = other.track()?;
+        let before = Snapshot::new(self.track()?, self.player_settings()?);
+        let after = Snapshot::new(other.track()?, other.player_settings()?);
 
-        let other_player_settings = other.player_settings()?;
+        macro_rules! event {
+            ($event: ident) => {
+                events.push(CmusEvent::$event(before.clone(), after.clone()))
+            };
+        }
 
-        if track != other_track {
+        if before.track.path != after.track.path {
             #[cfg(feature = "debug")]
-            debug!("Track changed: {:?} -> {:?}", other_track, track);
-
-            if track.path != other_track.path {
+            debug!("Track changed: {:?} -> {:?}", before.track, after.track);
+            event!(TrackChanged);
+        } else if before.track != after.track {
+            if before.track.stream != after.track.stream {
                 #[cfg(feature = "debug")]
-                debug!("Track changed: {:?} -> {:?}", other_track, track);
-                events.push(CmusEvent::TrackChanged(
-                    other_track,
-                    other_player_settings,
-                ));
-                // We don't need to check for other changes, since the track changed.
-                return Ok(events);
-            } else if track.status != other_track.status {
+                debug!(
+                    "Stream title changed: {:?} -> {:?}",
+                    before.track.stream, after.track.stream
+                );
+                event!(StreamTitleChanged);
+            }
+
+            if before.track.status != after.track.status {
                 #[cfg(feature = "debug")]
                 debug!(
                     "Status changed: {:?} -> {:?}",
-                    other_track.status, track.status
+                    before.track.status, after.track.status
                 );
-                events.push(CmusEvent::StatusChanged(
-                    other_track.clone(),
-                    other_player_settings.clone(),
-                ));
-            } else if track.position != other_track.position {
+                event!(StatusChanged);
+            }
+
+            if before.track.position != after.track.position {
                 #[cfg(feature = "debug")]
                 debug!(
                     "Position changed: {:?} -> {:?}",
-                    other_track.position, track.position
+                    before.track.position, after.track.position
                 );
-                events.push(CmusEvent::PositionChanged(
-                    track,
-                    other_player_settings.clone(),
-                ));
+                event!(PositionChanged);
+
+                if is_seek(&before.track, &after.track, played) {
+                    #[cfg(feature = "debug")]
+                    debug!(
+                        "Seeked: {:?} -> {:?}",
+                        before.track.position, after.track.position
+                    );
+                    event!(Seeked);
+                }
             }
         }
 
-        let player_settings = self.player_settings()?;
+        let (old, new) = (&before.player_settings, &after.player_settings);
 
-        if player_settings != other_player_settings {
+        if old != new {
             #[cfg(feature = "debug")]
-            debug!(
-                "Player settings changed: {:?} -> {:?}",
-                other_player_settings, player_settings
-            );
+            debug!("Player settings changed: {:?} -> {:?}", old, new);
+
+            if old.shuffle != new.shuffle {
+                #[cfg(feature = "debug")]
+                debug!("Shuffle changed: {:?} -> {:?}", old.shuffle, new.shuffle);
+                event!(ShuffleChanged);
+            }
 
-            if player_settings.shuffle != other_player_settings.shuffle {
+            if old.repeat != new.repeat {
+                #[cfg(feature = "debug")]
+                debug!("Repeat changed: {:?} -> {:?}", old.repeat, new.repeat);
+                event!(RepeatChanged);
+            }
+
+            if old.repeat_current != new.repeat_current {
                 #[cfg(feature = "debug")]
                 debug!(
-                    "Shuffle changed: {:?} -> {:?}",
-                    other_player_settings.shuffle, player_settings.shuffle
+                    "Repeat current changed: {:?} -> {:?}",
+                    old.repeat_current, new.repeat_current
                 );
+                event!(RepeatCurrentChanged);
+            }
 
-                events.push(CmusEvent::ShuffleChanged(
-                    other_track.clone(),
-                    other_player_settings.clone(),
-                ));
+            if old.aaa_mode != new.aaa_mode {
+                #[cfg(feature = "debug")]
+                debug!("AAA mode changed: {:?} -> {:?}", old.aaa_mode, new.aaa_mode);
+                event!(AAAModeChanged);
             }
 
-            if player_settings.repeat != other_player_settings.repeat {
+            if old.volume != new.volume {
+                #[cfg(feature = "debug")]
+                debug!("Volume changed: {:?} -> {:?}", old.volume, new.volume);
+                event!(VolumeChanged);
+            }
+
+            if old.continue_playing != new.continue_playing {
                 #[cfg(feature = "debug")]
                 debug!(
-                    "Repeat changed: {:?} -> {:?}",
-                    other_player_settings.repeat, player_settings.repeat
+                    "Continue changed: {:?} -> {:?}",
+                    old.continue_playing, new.continue_playing
                 );
-
-                events.push(CmusEvent::RepeatChanged(
-                    other_track.clone(),
-                    other_player_settings.clone(),
-                ));
+                event!(ContinueChanged);
             }
 
-            if player_settings.aaa_mode != other_player_settings.aaa_mode {
+            if old.play_library != new.play_library {
                 #[cfg(feature = "debug")]
                 debug!(
-                    "AAA mode changed: {:?} -> {:?}",
-                    other_player_settings.aaa_mode, player_settings.aaa_mode
+                    "Play library changed: {:?} -> {:?}",
+                    old.play_library, new.play_library
                 );
+                event!(PlayLibraryChanged);
+            }
 
-                events.push(CmusEvent::AAAModeChanged(
-                    other_track.clone(),
-                    other_player_settings.clone(),
-                ));
+            if old.play_sorted != new.play_sorted {
+                #[cfg(feature = "debug")]
+                debug!(
+                    "Play sorted changed: {:?} -> {:?}",
+                    old.play_sorted, new.play_sorted
+                );
+                event!(PlaySortedChanged);
             }
 
-            if player_settings.volume != other_player_settings.volume {
+            if old.replaygain != new.replaygain
+                || old.replaygain_limit != new.replaygain_limit
+                || old.replaygain_preamp != new.replaygain_preamp
+            {
                 #[cfg(feature = "debug")]
                 debug!(
-                    "Volume changed: {:?} -> {:?}",
-                    other_player_settings.volume, player_settings.volume
+                    "ReplayGain changed: {:?} -> {:?}",
+                    old.replaygain, new.replaygain
                 );
+                event!(ReplayGainChanged);
+            }
 
-                events.push(CmusEvent::VolumeChanged(other_track, other_player_settings));
+            if old.softvol != new.softvol {
+                #[cfg(feature = "debug")]
+                debug!("Softvol changed: {:?} -> {:?}", old.softvol, new.softvol);
+                event!(SoftVolChanged);
             }
         }
 
@@ -165,11 +236,27 @@ impl CmusQueryResponse {
     }
 }
 
+/// Returns true if the position moved further than the playback could move it in the played time,
+/// the position can move forward by the played time only.
+///
+/// Stopping resets the position, and so does replaying the same track from the start
+/// (e.g. with the repeat current mode), so neither is a seek.
+fn is_seek(before: &Track, after: &Track, played: Duration) -> bool {
+    if before.status == TrackStatus::Stopped || after.status == TrackStatus::Stopped {
+        return false;
+    }
+    let (from, to) = (before.position as u64, after.position as u64);
+    let restarted = before.duration > 0
+        && from + SEEK_TOLERANCE >= before.duration as u64
+        && to <= SEEK_TOLERANCE;
+
+    !restarted && (to + SEEK_TOLERANCE < from || to > from + played.as_secs() + SEEK_TOLERANCE)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cmus::player_settings::{AAAMode, Shuffle};
-    use crate::cmus::TrackStatus;
+    use crate::cmus::player_settings::{AAAMode, ReplayGain, Shuffle};
     use test_context::{test_context, TestContext};
 
     #[test]
@@ -235,6 +322,148 @@ mod tests {
         assert_eq!(metadata.get("comment"), None);
     }
 
+    #[test]
+    fn test_emit_the_stream_title_changed_event() {
+        let row = include_str!("../../tests/samples/cmus-remote-output-stream.txt");
+        let query = CmusQueryResponse::from_str(row).unwrap();
+        let other = CmusQueryResponse::from_str(
+            &row.replace("Boards of Canada - Roygbiv", "Tycho - Awake")
+                .replace("position 731", "position 732"),
+        )
+        .unwrap();
+
+        let events = query.events(&other, Duration::from_secs(1)).unwrap();
+
+        assert!(matches!(
+            events.first(),
+            Some(CmusEvent::StreamTitleChanged(before, after))
+                if before.track.stream.as_deref() == Some("Boards of Canada - Roygbiv")
+                    && after.track.stream.as_deref() == Some("Tycho - Awake")
+        ));
+    }
+
+    #[test_context(Context)]
+    #[test]
+    fn test_emit_an_event_for_every_changed_player_setting(ctx: &Context) {
+        let row = include_str!("../../tests/samples/row/cmus-remote-output-row.txt")
+            .replace("set repeat_current false", "set repeat_current true")
+            .replace("set continue true", "set continue false")
+            .replace("set play_library false", "set play_library true")
+            .replace("set replaygain_preamp 0.000000", "set replaygain_preamp 3.000000")
+            .replace("set softvol false", "set softvol true");
+        let other = CmusQueryResponse::from_str(&row).unwrap();
+
+        let events = ctx.query.events(&other, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[0], CmusEvent::RepeatCurrentChanged(_, _)));
+        assert!(matches!(events[1], CmusEvent::ContinueChanged(_, _)));
+        assert!(matches!(events[2], CmusEvent::PlayLibraryChanged(_, _)));
+        assert!(matches!(events[3], CmusEvent::ReplayGainChanged(_, _)));
+        assert!(matches!(events[4], CmusEvent::SoftVolChanged(_, _)));
+    }
+
+    #[test_context(Context)]
+    #[test]
+    fn test_emit_both_the_status_and_the_position_changes(ctx: &Context) {
+        let row = include_str!("../../tests/samples/row/cmus-remote-output-row.txt")
+            .replace("status playing", "status paused")
+            .replace("position 34", "position 35");
+        let other = CmusQueryResponse::from_str(&row).unwrap();
+
+        let events = ctx.query.events(&other, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], CmusEvent::StatusChanged(_, _)));
+        assert!(matches!(events[1], CmusEvent::PositionChanged(_, _)));
+    }
+
+    #[test_context(Context)]
+    #[test]
+    fn test_detect_the_seeks(ctx: &Context) {
+        let seeked = |position: &str, played: u64| {
+            let row = include_str!("../../tests/samples/row/cmus-remote-output-row.txt")
+                .replace("position 34", position);
+            let other = CmusQueryResponse::from_str(&row).unwrap();
+            ctx.query
+                .events(&other, Duration::from_secs(played))
+                .unwrap()
+                .iter()
+                .any(|event| matches!(event, CmusEvent::Seeked(_, _)))
+        };
+
+        // The normal playback.
+        assert!(!seeked("position 35", 1));
+        assert!(!seeked("position 64", 30));
+        // Forward and backward seeks.
+        assert!(seeked("position 80", 1));
+        assert!(seeked("position 10", 1));
+        // The time spent paused doesn't move the position.
+        assert!(seeked("position 64", 0));
+    }
+
+    #[test_context(Context)]
+    #[test]
+    fn test_dont_take_the_stop_for_a_seek(ctx: &Context) {
+        let row = include_str!("../../tests/samples/row/cmus-remote-output-row.txt")
+            .replace("status playing", "status stopped")
+            .replace("position 34", "position 0");
+        let other = CmusQueryResponse::from_str(&row).unwrap();
+
+        let events = ctx.query.events(&other, Duration::from_secs(1)).unwrap();
+
+        assert!(events.iter().any(|event| matches!(event, CmusEvent::StatusChanged(_, _))));
+        assert!(!events.iter().any(|event| matches!(event, CmusEvent::Seeked(_, _))));
+    }
+
+    #[test]
+    fn test_dont_take_the_restart_of_the_same_track_for_a_seek() {
+        let row = include_str!("../../tests/samples/row/cmus-remote-output-row.txt");
+        let seeked = |from: &str, to: &str| {
+            let query = CmusQueryResponse::from_str(&row.replace("position 34", from)).unwrap();
+            let other = CmusQueryResponse::from_str(&row.replace("position 34", to)).unwrap();
+            query
+                .events(&other, Duration::from_secs(2))
+                .unwrap()
+                .iter()
+                .any(|event| matches!(event, CmusEvent::Seeked(_, _)))
+        };
+
+        // The track (242 seconds long) ended, and started over.
+        assert!(!seeked("position 241", "position 1"));
+        assert!(!seeked("position 242", "position 0"));
+        // But seeking to the start from the middle of the track is still a seek.
+        assert!(seeked("position 120", "position 0"));
+    }
+
+    #[test]
+    fn test_tell_if_cmus_is_playing() {
+        let row = include_str!("../../tests/samples/row/cmus-remote-output-row.txt");
+
+        assert!(CmusQueryResponse::from_str(row).unwrap().is_playing());
+        assert!(!CmusQueryResponse::from_str(&row.replace("status playing", "status paused"))
+            .unwrap()
+            .is_playing());
+        assert!(!CmusQueryResponse::default().is_playing());
+    }
+
+    #[test_context(Context)]
+    #[test]
+    fn test_coalesce_the_track_changes_but_keep_the_player_settings_changes(ctx: &Context) {
+        let row = include_str!("../../tests/samples/row/cmus-remote-output-row.txt")
+            .replace("12 - Haunted.mp3", "13 - Last Kiss.mp3")
+            .replace("position 34", "position 0")
+            .replace("set vol_left 17", "set vol_left 20")
+            .replace("set vol_right 17", "set vol_right 20");
+        let other = CmusQueryResponse::from_str(&row).unwrap();
+
+        let events = ctx.query.events(&other, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], CmusEvent::TrackChanged(_, _)));
+        assert!(matches!(events[1], CmusEvent::VolumeChanged(_, _)));
+    }
+
     #[test_context(Context)]
     #[test]
     fn test_actually_parse_the_player_settings(ctx: &Context) {
@@ -249,5 +478,12 @@ mod tests {
         assert_eq!(player_settings.shuffle, Shuffle::Off);
         assert_eq!(player_settings.volume.left, 17);
         assert_eq!(player_settings.volume.right, 17);
+        assert!(player_settings.continue_playing);
+        assert!(!player_settings.play_library);
+        assert!(player_settings.play_sorted);
+        assert_eq!(player_settings.replaygain, ReplayGain::Disabled);
+        assert!(player_settings.replaygain_limit);
+        assert_eq!(player_settings.replaygain_preamp, 0.0);
+        assert!(!player_settings.softvol);
     }
 }
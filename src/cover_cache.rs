@@ -0,0 +1,377 @@
+//! A cache of the resolved covers on the disk, under "$XDG_CACHE_HOME/cmus-notify/covers".
+//!
+//! The embedded covers are keyed by the album and the album artist (or by the track path, if the track has no album),
+//! so the tracks of an album share the entry, and skipping through an album doesn't decode the embedded picture again.
+//! The external covers are keyed by what their search depends on, the rendered cover path template,
+//! the track directory and the track name, so the directories are not searched again.
+//!
+//! The embedded covers, and the external ones that need resizing, are stored as resized PNG files,
+//! and the other external ones as their path. Every entry remembers its source file (the track, or the external cover)
+//! and its modification time, so the entry is not used anymore when the source changes or disappears.
+use crate::cmus::Track;
+use crate::cover_resize::CoverResize;
+use crate::settings::Settings;
+use crate::TrackCover;
+#[cfg(feature = "debug")]
+use log::{debug, info};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Returns the cache directory, "$XDG_CACHE_HOME/cmus-notify/covers", or "~/.cache/cmus-notify/covers".
+pub fn cache_directory() -> Option<PathBuf> {
+    let cache_home = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(cache_home) if !cache_home.is_empty() => PathBuf::from(cache_home),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache_home.join("cmus-notify").join("covers"))
+}
+
+/// A stable 64-bit FNV-1a hash, the entries must have the same names in every run.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Returns the modification time of the file, in nanoseconds since the epoch, or `None` if it doesn't exist.
+fn modified(path: &str) -> Option<u128> {
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+/// The kind of the cached entries, the cached PNG file, or the path of the external cover.
+const CACHED_ENTRY: &str = "cached";
+const EXTERNAL_ENTRY: &str = "external";
+
+pub struct CoverCache {
+    directory: PathBuf,
+    resize: CoverResize,
+    /// The settings that change the resolved cover, so changing them doesn't use the old entries.
+    fingerprint: String,
+}
+
+impl CoverCache {
+    pub fn new(directory: PathBuf, resize: CoverResize, fingerprint: String) -> Self {
+        Self {
+            directory,
+            resize,
+            fingerprint,
+        }
+    }
+
+    /// Returns the cache of the settings, or `None` if it's disabled, or there's no cache directory.
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        if settings.no_cover_cache {
+            return None;
+        }
+        let resize = settings.cover_resize();
+        let fingerprint = format!(
+            "{:?}|{}|{}|{}|{:?}|{}|{}|{:?}|{}",
+            settings.cover_path_template,
+            settings.depth(),
+            settings.force_use_external_cover,
+            settings.no_use_external_cover,
+            settings.cover_picture_types(),
+            settings.cover_min_size(),
+            resize.max_size,
+            resize.filter,
+            resize.square,
+        );
+        Some(Self::new(cache_directory()?, resize, fingerprint))
+    }
+
+    fn hashed_key(&self, source: &str) -> String {
+        format!(
+            "{:016x}",
+            hash(format!("{source}\0{}", self.fingerprint).as_bytes())
+        )
+    }
+
+    /// Returns the name of the embedded cover entry of the track, shared by the album tracks, without the extension.
+    pub fn embedded_key(&self, track: &Track) -> String {
+        let album = track.metadata.get("album").unwrap_or_default();
+        if album.is_empty() {
+            return self.hashed_key(&format!("path\0{}", track.path));
+        }
+        let album_artist = track
+            .metadata
+            .get("albumartist")
+            .filter(|artist| !artist.is_empty())
+            .or_else(|| track.metadata.get("artist"))
+            .unwrap_or_default();
+        self.hashed_key(&format!("album\0{album}\0{album_artist}"))
+    }
+
+    /// Returns the name of the external cover entry of the track, without the extension,
+    /// with the rendered cover path template, if it's set.
+    /// The search in the track directory uses the track name, so they're a part of the key too.
+    pub fn external_key(&self, track: &Track, cover_path: Option<&str>) -> String {
+        let directory = Path::new(&track.path)
+            .parent()
+            .and_then(Path::to_str)
+            .unwrap_or_default();
+        self.hashed_key(&format!(
+            "external\0{}\0{directory}\0{}",
+            cover_path.unwrap_or_default(),
+            track.get_name()
+        ))
+    }
+
+    fn entry(&self, key: &str, extension: &str) -> PathBuf {
+        self.directory.join(format!("{key}.{extension}"))
+    }
+
+    /// Returns the cached cover of the entry, if there's one, and its source file didn't change since it was stored.
+    pub fn get(&self, key: &str) -> Option<TrackCover> {
+        let entry = std::fs::read_to_string(self.entry(key, "source")).ok()?;
+        let mut fields = entry.splitn(3, '\t');
+        let (modified_at, kind, source) = (fields.next()?, fields.next()?, fields.next()?);
+        if modified(source)?.to_string() != modified_at {
+            #[cfg(feature = "debug")]
+            debug!("The source of the cached cover \"{source}\" changed.");
+            return None;
+        }
+
+        match kind {
+            CACHED_ENTRY => {
+                let cached = self.entry(key, "png");
+                if !cached.is_file() {
+                    return None;
+                }
+                #[cfg(feature = "debug")]
+                debug!("Using the cached cover \"{}\".", cached.display());
+                Some(TrackCover::Cached(cached.to_str()?.to_string()))
+            }
+            EXTERNAL_ENTRY => {
+                #[cfg(feature = "debug")]
+                debug!("Using the cached external cover path \"{source}\".");
+                Some(TrackCover::External(source.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Store the resolved cover of the track, and returns it, the embedded cover (or the resized external one)
+    /// is returned as the cached file, so the notification server gets a path instead of the pixels.
+    /// If the cover can't be stored, it's returned as is.
+    pub fn store(&self, key: &str, cover: TrackCover, track_path: &str) -> TrackCover {
+        // The embedded cover changes with its track, and the external one with its own file.
+        let source = match &cover {
+            TrackCover::External(path) => path.clone(),
+            TrackCover::Embedded(_) => track_path.to_string(),
+            TrackCover::Cached(_) | TrackCover::None => return cover,
+        };
+        let Some(modified_at) = modified(&source) else {
+            return cover;
+        };
+
+        let resized = match &cover {
+            TrackCover::Embedded(image) => Some(self.resize.apply(image.clone())),
+            TrackCover::External(path) => self.resize.apply_to_file(path),
+            TrackCover::Cached(_) | TrackCover::None => None,
+        };
+        let (stored, kind) = match resized {
+            Some(image) => match self.store_image(key, &image) {
+                Ok(path) => (TrackCover::Cached(path), CACHED_ENTRY),
+                Err(_e) => {
+                    #[cfg(feature = "debug")]
+                    info!("Could not cache the cover: {_e}");
+                    return cover;
+                }
+            },
+            None => (cover, EXTERNAL_ENTRY),
+        };
+
+        let entry = format!("{modified_at}\t{kind}\t{source}");
+        let _ = std::fs::create_dir_all(&self.directory)
+            .and_then(|_| write_atomically(&self.entry(key, "source"), entry.as_bytes()));
+        stored
+    }
+
+    fn store_image(&self, key: &str, image: &image::DynamicImage) -> std::io::Result<String> {
+        std::fs::create_dir_all(&self.directory)?;
+        let mut png = Vec::new();
+        image
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .map_err(std::io::Error::other)?;
+
+        let path = self.entry(key, "png");
+        write_atomically(&path, &png)?;
+        path.to_str()
+            .map(str::to_string)
+            .ok_or_else(|| std::io::Error::other("The cache path is not valid UTF-8"))
+    }
+}
+
+/// Write the file under a temporary name first, so another instance never reads a half written file.
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let temporary = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn cache(name: &str) -> CoverCache {
+        let directory = std::env::temp_dir().join(format!(
+            "cmus-notify-cover-cache-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let resize = CoverResize {
+            max_size: 512,
+            filter: image::imageops::FilterType::Triangle,
+            square: false,
+        };
+        CoverCache::new(directory, resize, "settings".to_string())
+    }
+
+    fn track(path: &str, album: &str, album_artist: &str) -> Track {
+        let mut track = Track {
+            path: path.to_string(),
+            ..Track::default()
+        };
+        track.metadata.set_if_missing("album", album);
+        track.metadata.set_if_missing("albumartist", album_artist);
+        track
+    }
+
+    /// Write the file, with the given modification time, in seconds since the epoch.
+    fn write_file(path: &Path, contents: &[u8], modified_at: u64) -> String {
+        std::fs::write(path, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(modified_at))
+            .unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_share_the_embedded_entry_between_the_album_tracks() {
+        let cache = cache("embedded-keys");
+        let always = track("/Music/Cinematic/08 - Always.mp3", "Cinematic", "Owl City");
+        let fiji_water = track(
+            "/Music/Cinematic/02 - Fiji Water.mp3",
+            "Cinematic",
+            "Owl City",
+        );
+        let live = track("/Music/Cinematic/Live.mp3", "Cinematic", "Another Owl City");
+        let untagged = track("/Music/untagged.mp3", "", "");
+
+        assert_eq!(cache.embedded_key(&always), cache.embedded_key(&fiji_water));
+        assert_ne!(cache.embedded_key(&always), cache.embedded_key(&live));
+        assert_ne!(
+            cache.embedded_key(&untagged),
+            cache.embedded_key(&track("/Music/untagged2.mp3", "", ""))
+        );
+        assert_ne!(
+            cache.embedded_key(&always),
+            CoverCache::new(
+                cache.directory.clone(),
+                cache.resize,
+                "other settings".to_string()
+            )
+            .embedded_key(&always)
+        );
+
+        let _ = std::fs::remove_dir_all(&cache.directory);
+    }
+
+    #[test]
+    fn test_key_the_external_covers_by_their_search() {
+        let cache = cache("external-keys");
+        let always = track("/Music/Cinematic/08 - Always.mp3", "Cinematic", "Owl City");
+        let fiji_water = track(
+            "/Music/Cinematic/02 - Fiji Water.mp3",
+            "Cinematic",
+            "Owl City",
+        );
+
+        // The default search looks for the track name.
+        assert_ne!(
+            cache.external_key(&always, None),
+            cache.external_key(&fiji_water, None)
+        );
+        // The rendered cover path can have the track placeholders.
+        assert_ne!(
+            cache.external_key(&always, Some("covers/Always.*")),
+            cache.external_key(&always, Some("covers/Fiji Water.*"))
+        );
+        assert_eq!(
+            cache.external_key(&always, Some("covers/Cinematic.*")),
+            cache.external_key(&always, Some("covers/Cinematic.*"))
+        );
+
+        let _ = std::fs::remove_dir_all(&cache.directory);
+    }
+
+    #[test]
+    fn test_store_the_embedded_cover_as_a_resized_png() {
+        let cache = cache("embedded");
+        let track_path = write_file(&cache.directory.join("track.mp3"), b"a track", 1_000);
+        let cover = TrackCover::Embedded(image::DynamicImage::new_rgb8(1024, 768));
+
+        let TrackCover::Cached(path) = cache.store("album", cover, &track_path) else {
+            panic!("The cover is not cached");
+        };
+        assert_eq!(cache.get("album"), Some(TrackCover::Cached(path.clone())));
+        assert_eq!(image::image_dimensions(&path).unwrap(), (512, 384));
+        // The track was tagged again, so its cover may have changed.
+        write_file(Path::new(&track_path), b"a retagged track", 2_000);
+        assert_eq!(cache.get("album"), None);
+
+        let _ = std::fs::remove_dir_all(&cache.directory);
+    }
+
+    #[test]
+    fn test_store_the_external_cover_path() {
+        let cache = cache("external");
+        let cover_path = write_file(&cache.directory.join("cover.jpg"), b"a picture", 1_000);
+        let cover = TrackCover::External(cover_path.clone());
+
+        assert_eq!(cache.get("album"), None);
+        cache.store("album", cover, "/Music/track.mp3");
+        assert_eq!(
+            cache.get("album"),
+            Some(TrackCover::External(cover_path.clone()))
+        );
+        // The cover was replaced, or removed, so it's not used anymore.
+        write_file(Path::new(&cover_path), b"another picture", 2_000);
+        assert_eq!(cache.get("album"), None);
+        cache.store(
+            "album",
+            TrackCover::External(cover_path.clone()),
+            "/Music/track.mp3",
+        );
+        std::fs::remove_file(&cover_path).unwrap();
+        assert_eq!(cache.get("album"), None);
+
+        let _ = std::fs::remove_dir_all(&cache.directory);
+    }
+
+    #[test]
+    fn test_store_the_large_external_cover_as_a_resized_png() {
+        let cache = cache("large-external");
+        let cover_path = cache.directory.join("cover.png");
+        image::DynamicImage::new_rgb8(600, 600)
+            .save(&cover_path)
+            .unwrap();
+        let cover = TrackCover::External(cover_path.to_str().unwrap().to_string());
+
+        let TrackCover::Cached(path) = cache.store("album", cover, "/Music/track.mp3") else {
+            panic!("The cover is not cached");
+        };
+        assert_eq!(image::image_dimensions(&path).unwrap(), (512, 512));
+        assert_eq!(cache.get("album"), Some(TrackCover::Cached(path)));
+
+        let _ = std::fs::remove_dir_all(&cache.directory);
+    }
+}
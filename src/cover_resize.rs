@@ -0,0 +1,118 @@
+//! Shrink the covers before they're sent to the notification server,
+//! a big embedded scan would make a huge D-Bus message, that some servers drop.
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+/// The names of the resampling filters in the settings, with their filters.
+pub const FILTERS: &[(&str, FilterType)] = &[
+    ("nearest", FilterType::Nearest),
+    ("triangle", FilterType::Triangle),
+    ("catmull_rom", FilterType::CatmullRom),
+    ("gaussian", FilterType::Gaussian),
+    ("lanczos3", FilterType::Lanczos3),
+];
+
+/// Parse a resampling filter name, e.g. "lanczos3".
+pub fn parse_filter(name: &str) -> Result<FilterType, String> {
+    FILTERS
+        .iter()
+        .find(|(filter_name, _)| filter_name.eq_ignore_ascii_case(name.trim()))
+        .map(|(_, filter)| *filter)
+        .ok_or_else(|| {
+            format!(
+                "Unknown resampling filter \"{name}\", it should be one of {}",
+                FILTERS
+                    .iter()
+                    .map(|(filter_name, _)| *filter_name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+/// How the cover is resized.
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+pub struct CoverResize {
+    /// The largest width and height, the cover keeps its aspect ratio.
+    pub max_size: u32,
+    pub filter: FilterType,
+    /// Crop the cover to a square, from its center, before it's resized.
+    pub square: bool,
+}
+
+impl CoverResize {
+    /// Returns true if an image with these dimensions would change.
+    pub fn is_needed(&self, width: u32, height: u32) -> bool {
+        width.max(height) > self.max_size || (self.square && width != height)
+    }
+
+    /// Returns the cropped and resized image, or the same image if it doesn't need any change.
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        let (width, height) = (image.width(), image.height());
+        if !self.is_needed(width, height) {
+            return image;
+        }
+        let image = if self.square && width != height {
+            let size = width.min(height);
+            image.crop_imm((width - size) / 2, (height - size) / 2, size, size)
+        } else {
+            image
+        };
+        if image.width().max(image.height()) > self.max_size {
+            image.resize(self.max_size, self.max_size, self.filter)
+        } else {
+            image
+        }
+    }
+
+    /// Returns the resized image file, or `None` if the file doesn't need any change (or it can't be read).
+    pub fn apply_to_file(&self, path: &str) -> Option<DynamicImage> {
+        let (width, height) = image::image_dimensions(path).ok()?;
+        if !self.is_needed(width, height) {
+            return None;
+        }
+        Some(self.apply(image::open(path).ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resize(max_size: u32, square: bool) -> CoverResize {
+        CoverResize {
+            max_size,
+            filter: FilterType::Triangle,
+            square,
+        }
+    }
+
+    fn dimensions(image: DynamicImage) -> (u32, u32) {
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn test_shrink_the_large_covers() {
+        let image = || DynamicImage::new_rgb8(300, 200);
+
+        assert_eq!(dimensions(resize(30, false).apply(image())), (30, 20));
+        assert_eq!(dimensions(resize(30, true).apply(image())), (30, 30));
+        assert_eq!(dimensions(resize(500, false).apply(image())), (300, 200));
+        assert_eq!(dimensions(resize(500, true).apply(image())), (200, 200));
+    }
+
+    #[test]
+    fn test_tell_if_the_cover_needs_a_change() {
+        assert!(!resize(256, false).is_needed(256, 200));
+        assert!(resize(256, true).is_needed(256, 200));
+        assert!(resize(256, false).is_needed(257, 200));
+    }
+
+    #[test]
+    fn test_parse_the_filters() {
+        assert_eq!(parse_filter("lanczos3"), Ok(FilterType::Lanczos3));
+        assert_eq!(parse_filter("Catmull_Rom"), Ok(FilterType::CatmullRom));
+        assert!(parse_filter("bicubic").is_err());
+    }
+}
@@ -0,0 +1,799 @@
+//! Read the pictures embedded in the track tags, without decoding them.
+//!
+//! * ID3v2 - the "APIC" frames (MP3, and the other files that start with an ID3 tag).
+//! * FLAC - the picture blocks, and the "METADATA_BLOCK_PICTURE" Vorbis comments.
+//! * Ogg (Opus and Vorbis) - the "METADATA_BLOCK_PICTURE" Vorbis comments.
+//! * MP4 (M4A, M4B) - the "covr" atom.
+//! * APEv2 (WavPack, Monkey's Audio, Musepack) - the "Cover Art (...)" items.
+//!
+//! The picture types are the ID3 ones, e.g. 3 is the front cover, and 4 is the back cover.
+//! Use `select_picture` to pick the cover among them.
+use base64::Engine;
+#[cfg(feature = "debug")]
+use log::info;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+
+/// The picture type of the front cover.
+pub const FRONT_COVER: u8 = 3;
+
+/// The names of the picture types in the settings, in the ID3 order.
+pub const PICTURE_TYPE_NAMES: &[&str] = &[
+    "other",
+    "icon",
+    "other_icon",
+    "front",
+    "back",
+    "leaflet",
+    "media",
+    "lead_artist",
+    "artist",
+    "conductor",
+    "band",
+    "composer",
+    "lyricist",
+    "recording_location",
+    "during_recording",
+    "during_performance",
+    "screen_capture",
+    "fish",
+    "illustration",
+    "band_logo",
+    "publisher_logo",
+];
+
+/// The name that matches any picture type.
+pub const ANY_PICTURE_TYPE: &str = "any";
+
+/// The largest block we read, so a broken file doesn't make us allocate gigabytes.
+const MAX_BLOCK_SIZE: usize = 64 * 1024 * 1024;
+
+const FLAC_VORBIS_COMMENT_BLOCK: u8 = 4;
+const FLAC_PICTURE_BLOCK: u8 = 6;
+
+/// The APEv2 cover items are "Cover Art (Front)", "Cover Art (Back)", ..., in the ID3 picture types order.
+const APE_COVER_TYPES: &[&str] = &[
+    "Other",
+    "Png Icon",
+    "Icon",
+    "Front",
+    "Back",
+    "Leaflet",
+    "Media",
+    "Lead Artist",
+    "Artist",
+    "Conductor",
+    "Band",
+    "Composer",
+    "Lyricist",
+    "Recording Location",
+    "During Recording",
+    "During Performance",
+    "Video Capture",
+    "Fish",
+    "Illustration",
+    "Band Logotype",
+    "Publisher Logotype",
+];
+
+/// A picture embedded in the track tags.
+#[derive(PartialEq)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+pub struct EmbeddedPicture {
+    /// The ID3 picture type, e.g. 3 for the front cover.
+    pub picture_type: u8,
+    /// The encoded image, e.g. a JPEG or a PNG file.
+    pub data: Vec<u8>,
+}
+
+/// Returns all the pictures embedded in the track, in the order they're stored.
+/// The format is detected from the file contents, not the extension.
+pub fn read_pictures(track_path: &str) -> io::Result<Vec<EmbeddedPicture>> {
+    #[cfg(feature = "debug")]
+    info!("Reading the embedded pictures of \"{track_path}\".");
+    read_pictures_from(&mut BufReader::new(File::open(track_path)?))
+}
+
+/// Like `read_pictures`, but from any reader.
+pub fn read_pictures_from<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<EmbeddedPicture>> {
+    let mut magic = [0u8; 8];
+    let read = reader.read(&mut magic)?;
+    reader.seek(SeekFrom::Start(0))?;
+    let magic = &magic[..read];
+
+    if magic.starts_with(b"fLaC") {
+        flac_pictures(reader)
+    } else if magic.starts_with(b"OggS") {
+        ogg_pictures(reader)
+    } else if magic.get(4..8) == Some(b"ftyp") {
+        mp4_pictures(reader)
+    } else if magic.starts_with(b"ID3") {
+        let pictures = id3_pictures(reader)?;
+        // Some MP3 files have the cover in an APEv2 tag instead.
+        if pictures.is_empty() {
+            ape_pictures(reader)
+        } else {
+            Ok(pictures)
+        }
+    } else {
+        ape_pictures(reader)
+    }
+}
+
+/// Parse a picture type name, e.g. "front", returns `None` for "any".
+pub fn parse_picture_type(name: &str) -> Result<Option<u8>, String> {
+    let name = name.trim();
+    if name.eq_ignore_ascii_case(ANY_PICTURE_TYPE) {
+        return Ok(None);
+    }
+    PICTURE_TYPE_NAMES
+        .iter()
+        .position(|type_name| type_name.eq_ignore_ascii_case(name))
+        .map(|position| Some(position as u8))
+        .ok_or_else(|| {
+            format!(
+                "Unknown picture type \"{name}\", it should be one of {}, or \"{ANY_PICTURE_TYPE}\"",
+                PICTURE_TYPE_NAMES.join(", ")
+            )
+        })
+}
+
+/// Select the cover among the pictures, the first one of the first type in the priority list
+/// (`None` matches any type) that's at least `min_size` pixels wide and high, and decode it.
+///
+/// Returns `None` if no picture qualifies, so the caller can look for an external cover.
+pub fn select_picture(
+    pictures: &[EmbeddedPicture],
+    priority: &[Option<u8>],
+    min_size: u32,
+) -> Option<image::DynamicImage> {
+    // Every picture is checked once, even if it matches many types in the list.
+    let mut rejected = vec![false; pictures.len()];
+
+    for wanted in priority {
+        for (index, picture) in pictures.iter().enumerate() {
+            if rejected[index] || wanted.is_some_and(|wanted| wanted != picture.picture_type) {
+                continue;
+            }
+            match decode_picture(picture, min_size) {
+                Some(image) => return Some(image),
+                None => rejected[index] = true,
+            }
+        }
+    }
+    None
+}
+
+/// Decode the picture, if it's large enough, the dimensions are read from the header first.
+fn decode_picture(picture: &EmbeddedPicture, min_size: u32) -> Option<image::DynamicImage> {
+    let reader = || {
+        image::ImageReader::new(Cursor::new(&picture.data))
+            .with_guessed_format()
+            .ok()
+    };
+    let (width, height) = reader()?.into_dimensions().ok()?;
+    if width.min(height) < min_size {
+        #[cfg(feature = "debug")]
+        info!(
+            "Skipping the embedded picture of type {}, it's only {width}x{height}.",
+            picture.picture_type
+        );
+        return None;
+    }
+    reader()?.decode().ok()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_bytes<R: Read>(reader: &mut R, length: usize) -> io::Result<Vec<u8>> {
+    if length > MAX_BLOCK_SIZE {
+        return Err(invalid_data("The block is too large"));
+    }
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// A cursor over a block that's already in memory.
+struct Bytes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if length > self.data.len() {
+            return Err(invalid_data("Unexpected end of the block"));
+        }
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u32_be(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u32_le(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+fn id3_pictures<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<EmbeddedPicture>> {
+    let tag = id3::Tag::read_from2(&mut *reader).map_err(io::Error::other)?;
+    Ok(tag
+        .pictures()
+        .map(|picture| EmbeddedPicture {
+            picture_type: picture.picture_type.into(),
+            data: picture.data.clone(),
+        })
+        .collect())
+}
+
+/// Parse a FLAC picture block, the same format is used (in base64) by the "METADATA_BLOCK_PICTURE" comments.
+fn parse_picture_block(block: &[u8]) -> io::Result<EmbeddedPicture> {
+    let mut bytes = Bytes { data: block };
+    let picture_type = bytes.u32_be()?;
+    let mime_length = bytes.u32_be()? as usize;
+    bytes.take(mime_length)?;
+    let description_length = bytes.u32_be()? as usize;
+    bytes.take(description_length)?;
+    // The width, the height, the color depth, and the number of the colors.
+    bytes.take(16)?;
+    let data_length = bytes.u32_be()? as usize;
+
+    Ok(EmbeddedPicture {
+        picture_type: u8::try_from(picture_type).unwrap_or(0),
+        data: bytes.take(data_length)?.to_vec(),
+    })
+}
+
+/// Returns the pictures of a Vorbis comment block (without the framing bit).
+fn vorbis_comment_pictures(block: &[u8]) -> io::Result<Vec<EmbeddedPicture>> {
+    let mut bytes = Bytes { data: block };
+    let vendor_length = bytes.u32_le()? as usize;
+    bytes.take(vendor_length)?;
+    let count = bytes.u32_le()?;
+
+    let mut pictures = Vec::new();
+    for _ in 0..count {
+        let length = bytes.u32_le()? as usize;
+        let comment = bytes.take(length)?;
+        let Some(separator) = comment.iter().position(|&b| b == b'=') else {
+            continue;
+        };
+        let (key, value) = (&comment[..separator], &comment[separator + 1..]);
+
+        // The old taggers wrote the image itself, without the picture block.
+        let legacy = key.eq_ignore_ascii_case(b"COVERART");
+        if !legacy && !key.eq_ignore_ascii_case(b"METADATA_BLOCK_PICTURE") {
+            continue;
+        }
+        let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(value) else {
+            continue;
+        };
+        if legacy {
+            pictures.push(EmbeddedPicture {
+                picture_type: FRONT_COVER,
+                data: decoded,
+            });
+        } else if let Ok(picture) = parse_picture_block(&decoded) {
+            pictures.push(picture);
+        }
+    }
+    Ok(pictures)
+}
+
+fn flac_pictures<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<EmbeddedPicture>> {
+    let mut pictures = Vec::new();
+    reader.seek(SeekFrom::Start(4))?;
+
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header)?;
+        let last = header[0] & 0x80 != 0;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+        match header[0] & 0x7f {
+            FLAC_PICTURE_BLOCK => pictures.push(parse_picture_block(&read_bytes(reader, length)?)?),
+            FLAC_VORBIS_COMMENT_BLOCK => {
+                pictures.extend(vorbis_comment_pictures(&read_bytes(reader, length)?)?)
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(length as i64))?;
+            }
+        }
+        // The audio frames follow the last metadata block.
+        if last {
+            break Ok(pictures);
+        }
+    }
+}
+
+/// The comments are in the second packet of the first logical stream,
+/// and it's usually split over many pages if it has a picture.
+fn ogg_pictures<R: Read>(reader: &mut R) -> io::Result<Vec<EmbeddedPicture>> {
+    let mut serial = None;
+    let mut packets = 0;
+    let mut packet = Vec::new();
+
+    loop {
+        let mut header = [0u8; 27];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != b"OggS" {
+            return Err(invalid_data("Invalid Ogg page"));
+        }
+        let page_serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+        let lacing = read_bytes(reader, header[26] as usize)?;
+        let body = read_bytes(reader, lacing.iter().map(|&l| l as usize).sum())?;
+        // Skip the pages of the other streams, if the file has many of them.
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+
+        let mut offset = 0;
+        for &segment in &lacing {
+            let segment = segment as usize;
+            if packets == 1 {
+                packet.extend_from_slice(&body[offset..offset + segment]);
+            }
+            offset += segment;
+            // A segment shorter than 255 bytes ends the packet.
+            if segment < 255 {
+                packets += 1;
+                if packets == 2 {
+                    return comment_packet_pictures(&packet);
+                }
+            }
+        }
+        if packet.len() > MAX_BLOCK_SIZE {
+            return Err(invalid_data("The comment packet is too large"));
+        }
+    }
+}
+
+fn comment_packet_pictures(packet: &[u8]) -> io::Result<Vec<EmbeddedPicture>> {
+    if let Some(comments) = packet.strip_prefix(b"OpusTags") {
+        vorbis_comment_pictures(comments)
+    } else if let Some(comments) = packet.strip_prefix(b"\x03vorbis") {
+        vorbis_comment_pictures(comments)
+    } else {
+        // Another codec, e.g. Speex, we don't know where its comments are.
+        Ok(Vec::new())
+    }
+}
+
+/// Find the atom with the given name, from the current position to the end,
+/// and returns the position of its contents, and the position of its end.
+fn find_atom<R: Read + Seek>(
+    reader: &mut R,
+    end: u64,
+    name: &[u8; 4],
+) -> io::Result<Option<(u64, u64)>> {
+    loop {
+        let position = reader.stream_position()?;
+        if position + 8 > end {
+            return Ok(None);
+        }
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let (mut size, mut header_size) = (
+            u32::from_be_bytes(header[..4].try_into().unwrap()) as u64,
+            8,
+        );
+        if size == 1 {
+            let mut large_size = [0u8; 8];
+            reader.read_exact(&mut large_size)?;
+            size = u64::from_be_bytes(large_size);
+            header_size = 16;
+        } else if size == 0 {
+            // The atom extends to the end.
+            size = end - position;
+        }
+        // The next atom must be after this one, so a bogus size can't loop or overflow.
+        let next = match position.checked_add(size) {
+            Some(next) if size >= header_size && next > position && next <= end => next,
+            _ => return Err(invalid_data("Invalid MP4 atom")),
+        };
+
+        if &header[4..] == name {
+            return Ok(Some((position + header_size, next)));
+        }
+        reader.seek(SeekFrom::Start(next))?;
+    }
+}
+
+/// The cover is in "moov/udta/meta/ilst/covr", with a "data" atom for every picture.
+fn mp4_pictures<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<EmbeddedPicture>> {
+    let mut end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    for name in [b"moov", b"udta", b"meta", b"ilst", b"covr"] {
+        let Some((start, atom_end)) = find_atom(reader, end, name)? else {
+            return Ok(Vec::new());
+        };
+        end = atom_end;
+        if name == b"meta" {
+            // The iTunes "meta" atom has the version and the flags before its children, the QuickTime one doesn't.
+            let mut child = [0u8; 8];
+            reader.read_exact(&mut child)?;
+            let start = if &child[4..] == b"hdlr" {
+                start
+            } else {
+                start + 4
+            };
+            reader.seek(SeekFrom::Start(start))?;
+        }
+    }
+
+    let mut pictures = Vec::new();
+    while let Some((start, data_end)) = find_atom(reader, end, b"data")? {
+        // The type (JPEG, PNG, ...), and the locale.
+        let header_size = 8;
+        if data_end < start + header_size {
+            return Err(invalid_data("Invalid MP4 data atom"));
+        }
+        reader.seek(SeekFrom::Start(start + header_size))?;
+        pictures.push(EmbeddedPicture {
+            picture_type: FRONT_COVER,
+            data: read_bytes(reader, (data_end - start - header_size) as usize)?,
+        });
+    }
+    Ok(pictures)
+}
+
+fn ape_cover_type(key: &str) -> Option<u8> {
+    let name = key.strip_prefix("Cover Art (")?.strip_suffix(')')?;
+    APE_COVER_TYPES
+        .iter()
+        .position(|cover_type| cover_type.eq_ignore_ascii_case(name))
+        .map(|position| position as u8)
+}
+
+/// The APEv2 tag is at the end of the file, before the ID3v1 tag if there's one.
+fn ape_pictures<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<EmbeddedPicture>> {
+    let length = reader.seek(SeekFrom::End(0))?;
+    let mut footer_end = length;
+    if length >= 128 {
+        reader.seek(SeekFrom::End(-128))?;
+        let mut id3v1 = [0u8; 3];
+        reader.read_exact(&mut id3v1)?;
+        if &id3v1 == b"TAG" {
+            footer_end -= 128;
+        }
+    }
+    if footer_end < 32 {
+        return Ok(Vec::new());
+    }
+
+    reader.seek(SeekFrom::Start(footer_end - 32))?;
+    let footer = read_bytes(reader, 32)?;
+    if &footer[..8] != b"APETAGEX" {
+        return Ok(Vec::new());
+    }
+    let mut bytes = Bytes {
+        data: &footer[12..20],
+    };
+    // The size includes the footer, but not the header.
+    let size = bytes.u32_le()? as u64;
+    let count = bytes.u32_le()?;
+    if size < 32 || size > footer_end {
+        return Err(invalid_data("Invalid APEv2 tag"));
+    }
+    reader.seek(SeekFrom::Start(footer_end - size))?;
+    let items = read_bytes(reader, (size - 32) as usize)?;
+
+    let mut bytes = Bytes { data: &items };
+    let mut pictures = Vec::new();
+    for _ in 0..count {
+        let value_length = bytes.u32_le()? as usize;
+        let _flags = bytes.u32_le()?;
+        let Some(key_length) = bytes.data.iter().position(|&b| b == 0) else {
+            return Err(invalid_data("Invalid APEv2 item"));
+        };
+        let key = String::from_utf8_lossy(bytes.take(key_length)?).into_owned();
+        bytes.take(1)?;
+        let value = bytes.take(value_length)?;
+
+        // The value is the picture file name, then the picture itself.
+        let Some(picture_type) = ape_cover_type(&key) else {
+            continue;
+        };
+        let Some(separator) = value.iter().position(|&b| b == 0) else {
+            continue;
+        };
+        pictures.push(EmbeddedPicture {
+            picture_type,
+            data: value[separator + 1..].to_vec(),
+        });
+    }
+    Ok(pictures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JPEG: &[u8] = b"\xff\xd8\xff\xe0 a jpeg picture";
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n a png picture";
+
+    fn picture_block(picture_type: u32, data: &[u8]) -> Vec<u8> {
+        let mut block = Vec::new();
+        block.extend(picture_type.to_be_bytes());
+        block.extend(10u32.to_be_bytes());
+        block.extend(b"image/jpeg");
+        block.extend(5u32.to_be_bytes());
+        block.extend(b"Cover");
+        block.extend([0; 16]);
+        block.extend((data.len() as u32).to_be_bytes());
+        block.extend(data);
+        block
+    }
+
+    fn vorbis_comments(comments: &[String]) -> Vec<u8> {
+        let mut block = Vec::new();
+        block.extend(6u32.to_le_bytes());
+        block.extend(b"vendor");
+        block.extend((comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            block.extend((comment.len() as u32).to_le_bytes());
+            block.extend(comment.as_bytes());
+        }
+        block
+    }
+
+    fn base64_picture(picture_type: u32, data: &[u8]) -> String {
+        format!(
+            "METADATA_BLOCK_PICTURE={}",
+            base64::engine::general_purpose::STANDARD.encode(picture_block(picture_type, data))
+        )
+    }
+
+    fn pictures(file: Vec<u8>) -> Vec<EmbeddedPicture> {
+        read_pictures_from(&mut Cursor::new(file)).unwrap()
+    }
+
+    #[test]
+    fn test_read_the_flac_pictures() {
+        let mut file = b"fLaC".to_vec();
+        let blocks = [
+            (0, vec![0; 34]),
+            (
+                FLAC_VORBIS_COMMENT_BLOCK,
+                vorbis_comments(&["TITLE=Fiji Water".to_string(), base64_picture(4, PNG)]),
+            ),
+            (FLAC_PICTURE_BLOCK | 0x80, picture_block(3, JPEG)),
+        ];
+        for (block_type, block) in blocks {
+            file.push(block_type);
+            file.extend(&(block.len() as u32).to_be_bytes()[1..]);
+            file.extend(block);
+        }
+        file.extend(b"audio frames");
+
+        assert_eq!(
+            pictures(file),
+            vec![
+                EmbeddedPicture {
+                    picture_type: 4,
+                    data: PNG.to_vec()
+                },
+                EmbeddedPicture {
+                    picture_type: 3,
+                    data: JPEG.to_vec()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_the_opus_pictures_split_over_many_pages() {
+        let packets = [
+            b"OpusHead and the rest".to_vec(),
+            [
+                b"OpusTags".to_vec(),
+                vorbis_comments(&[base64_picture(3, &[7; 1000])]),
+            ]
+            .concat(),
+            b"audio".to_vec(),
+        ];
+        // Lay the packets out in segments, and put 2 segments in every page.
+        let mut segments = Vec::new();
+        for packet in &packets {
+            let mut chunks = packet.chunks(255).collect::<Vec<_>>();
+            if packet.len() % 255 == 0 {
+                chunks.push(&[]);
+            }
+            segments.extend(chunks);
+        }
+        let mut file = Vec::new();
+        for page in segments.chunks(2) {
+            file.extend(b"OggS\0\0");
+            file.extend([0; 8]);
+            file.extend(1234u32.to_le_bytes());
+            file.extend([0; 8]);
+            file.push(page.len() as u8);
+            file.extend(page.iter().map(|segment| segment.len() as u8));
+            file.extend(page.concat());
+        }
+
+        assert_eq!(
+            pictures(file),
+            vec![EmbeddedPicture {
+                picture_type: 3,
+                data: vec![7; 1000]
+            }]
+        );
+    }
+
+    fn atom(name: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        [
+            &((contents.len() + 8) as u32).to_be_bytes(),
+            &name[..],
+            contents,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_read_the_mp4_pictures() {
+        let data = |picture: &[u8]| atom(b"data", &[&[0, 0, 0, 13, 0, 0, 0, 0], picture].concat());
+        let covr = atom(b"covr", &[data(JPEG), data(PNG)].concat());
+        let meta = atom(
+            b"meta",
+            &[&[0; 4], &atom(b"hdlr", &[0; 25])[..], &atom(b"ilst", &covr)].concat(),
+        );
+        let moov = atom(
+            b"moov",
+            &[atom(b"mvhd", &[0; 100]), atom(b"udta", &meta)].concat(),
+        );
+        let file = [
+            atom(b"ftyp", b"M4A mp42isom"),
+            moov,
+            atom(b"mdat", b"audio"),
+        ]
+        .concat();
+
+        assert_eq!(
+            pictures(file),
+            vec![
+                EmbeddedPicture {
+                    picture_type: FRONT_COVER,
+                    data: JPEG.to_vec()
+                },
+                EmbeddedPicture {
+                    picture_type: FRONT_COVER,
+                    data: PNG.to_vec()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reject_the_overflowing_mp4_atom() {
+        // A large size that would wrap the position around to the start of the file.
+        let file = [
+            &atom(b"ftyp", b"M4A mp42isom")[..],
+            &[0, 0, 0, 1],
+            b"free",
+            &(u64::MAX - 19).to_be_bytes(),
+        ]
+        .concat();
+
+        assert!(find_atom(&mut Cursor::new(&file), file.len() as u64, b"moov").is_err());
+    }
+
+    #[test]
+    fn test_read_the_apev2_pictures() {
+        let item = |key: &str, value: &[u8]| {
+            [
+                &(value.len() as u32).to_le_bytes()[..],
+                &[2, 0, 0, 0],
+                key.as_bytes(),
+                &[0],
+                value,
+            ]
+            .concat()
+        };
+        let items = [
+            item("Title", b"Always"),
+            item("Cover Art (Back)", &[b"back.png\0", PNG].concat()),
+            item("Cover Art (Front)", &[b"front.jpg\0", JPEG].concat()),
+        ]
+        .concat();
+        let mut footer = b"APETAGEX".to_vec();
+        footer.extend(2000u32.to_le_bytes());
+        footer.extend((items.len() as u32 + 32).to_le_bytes());
+        footer.extend(3u32.to_le_bytes());
+        footer.extend([0; 12]);
+        let id3v1 = [&b"TAG"[..], &[0; 125]].concat();
+        let file = [&b"wvpk audio"[..], &items, &footer, &id3v1].concat();
+
+        assert_eq!(
+            pictures(file),
+            vec![
+                EmbeddedPicture {
+                    picture_type: 4,
+                    data: PNG.to_vec()
+                },
+                EmbeddedPicture {
+                    picture_type: 3,
+                    data: JPEG.to_vec()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_nothing_from_an_untagged_file() {
+        assert_eq!(pictures(b"just some audio".to_vec()), Vec::new());
+        assert_eq!(pictures(Vec::new()), Vec::new());
+    }
+
+    fn png(size: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        image::RgbImage::new(size, size)
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_select_the_picture_by_the_type_priority() {
+        let pictures = vec![
+            EmbeddedPicture {
+                picture_type: 8,
+                data: png(300),
+            },
+            EmbeddedPicture {
+                picture_type: 4,
+                data: png(200),
+            },
+            EmbeddedPicture {
+                picture_type: FRONT_COVER,
+                data: png(100),
+            },
+        ];
+        let width = |priority: &[Option<u8>]| {
+            select_picture(&pictures, priority, 0).map(|image| image.width())
+        };
+
+        assert_eq!(width(&[Some(FRONT_COVER), Some(6), None]), Some(100));
+        assert_eq!(width(&[Some(6), None]), Some(300));
+        assert_eq!(width(&[Some(6)]), None);
+    }
+
+    #[test]
+    fn test_skip_the_small_and_the_broken_pictures() {
+        let pictures = vec![
+            EmbeddedPicture {
+                picture_type: FRONT_COVER,
+                data: b"not a picture".to_vec(),
+            },
+            EmbeddedPicture {
+                picture_type: 1,
+                data: png(32),
+            },
+            EmbeddedPicture {
+                picture_type: 6,
+                data: png(128),
+            },
+        ];
+        let priority = [Some(FRONT_COVER), Some(6), None];
+
+        assert_eq!(
+            select_picture(&pictures, &priority, 64).map(|image| image.width()),
+            Some(128)
+        );
+        assert_eq!(select_picture(&pictures, &priority, 256), None);
+    }
+
+    #[test]
+    fn test_parse_the_picture_types() {
+        assert_eq!(parse_picture_type("front"), Ok(Some(3)));
+        assert_eq!(parse_picture_type("Media"), Ok(Some(6)));
+        assert_eq!(parse_picture_type("any"), Ok(None));
+        assert!(parse_picture_type("poster").is_err());
+    }
+}
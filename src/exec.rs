@@ -0,0 +1,228 @@
+//! The "{exec:NAME}" placeholders, replaced with the output of the commands configured in the settings.
+//!
+//! The commands run with `sh -c`, with the track fields in the environment
+//! (e.g. "$CMUS_FILE", "$CMUS_TITLE", "$CMUS_ARTIST"), and they are killed if they don't finish in time.
+//! The output is cached per track, so the live notifications don't run the commands on every refresh.
+use crate::cmus::Track;
+#[cfg(feature = "debug")]
+use log::{debug, info};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use wait_timeout::ChildExt;
+
+/// The prefix of the command placeholders, e.g. "{exec:lastfm}".
+pub const EXEC_PREFIX: &str = "exec:";
+
+/// How many outputs we keep, before we forget them all.
+const MAX_CACHED_OUTPUTS: usize = 64;
+
+/// The configured commands, by their name, with the cache of their outputs.
+#[derive(Default)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+pub struct ExecCommands {
+    commands: HashMap<String, String>,
+    timeout: Duration,
+    /// The outputs by the command name and the track, `None` if the command failed or timed out.
+    cache: RefCell<HashMap<(String, String), Option<String>>>,
+}
+
+impl ExecCommands {
+    /// Build the commands from their definitions, "NAME=COMMAND", the invalid ones are skipped
+    /// (they're reported by `Settings::validate_templates`).
+    pub fn new(definitions: &[String], timeout: Duration) -> Self {
+        Self {
+            commands: definitions
+                .iter()
+                .filter_map(|definition| parse_definition(definition).ok())
+                .map(|(name, command)| (name.to_string(), command.to_string()))
+                .collect(),
+            timeout,
+            cache: RefCell::default(),
+        }
+    }
+
+    /// Returns true if a command with this name is configured.
+    #[inline(always)]
+    pub fn contains(&self, name: &str) -> bool {
+        self.commands.contains_key(name)
+    }
+
+    /// Returns the output of the command for this track, running it only if it didn't run for this track before.
+    pub fn value(&self, name: &str, track: &Track) -> Option<String> {
+        let command = self.commands.get(name)?;
+        // A stream plays many songs with the same path.
+        let track_key = format!("{}\0{}", track.path, track.stream.as_deref().unwrap_or_default());
+        let cache_key = (name.to_string(), track_key);
+
+        if let Some(output) = self.cache.borrow().get(&cache_key) {
+            return output.clone();
+        }
+
+        let output = self.run(command, track);
+        let mut cache = self.cache.borrow_mut();
+        if cache.len() >= MAX_CACHED_OUTPUTS {
+            cache.clear();
+        }
+        cache.insert(cache_key, output.clone());
+        output
+    }
+
+    /// Run the command, and returns its output without the trailing new lines,
+    /// or `None` if it failed, or didn't finish in time.
+    fn run(&self, command: &str, track: &Track) -> Option<String> {
+        #[cfg(feature = "debug")]
+        info!("Running the command \"{command}\" for \"{}\".", track.path);
+
+        // The command and the reading of its output share the same time.
+        let deadline = Instant::now() + self.timeout;
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(track_environment(track))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+
+        // Read the output in another thread, so a command with a long output doesn't block on a full pipe.
+        let mut stdout = child.stdout.take()?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stdout.read_to_string(&mut output);
+            let _ = sender.send(output);
+        });
+
+        match child.wait_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Some(status)) if status.success() => receiver
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .ok()
+                .map(|output| output.trim_end_matches(['\n', '\r']).to_string()),
+            Ok(Some(_status)) => {
+                #[cfg(feature = "debug")]
+                debug!("The command \"{command}\" failed: {_status}");
+                None
+            }
+            _ => {
+                #[cfg(feature = "debug")]
+                debug!("The command \"{command}\" timed out, killing it.");
+                let _ = child.kill();
+                let _ = child.wait();
+                None
+            }
+        }
+    }
+}
+
+/// Split the command definition "NAME=COMMAND" into the name and the command.
+pub fn parse_definition(definition: &str) -> Result<(&str, &str), String> {
+    match definition.split_once('=') {
+        Some((name, command)) if !name.trim().is_empty() && !command.trim().is_empty() => {
+            Ok((name.trim(), command))
+        }
+        _ => Err(format!(
+            "Invalid command \"{definition}\", it should be like \"NAME=COMMAND\""
+        )),
+    }
+}
+
+/// The environment of the commands, the track fields and its tags, e.g. "CMUS_TITLE".
+fn track_environment(track: &Track) -> Vec<(String, String)> {
+    let mut environment = vec![
+        ("CMUS_FILE".to_string(), track.path.clone()),
+        ("CMUS_STATUS".to_string(), track.status.to_string()),
+        ("CMUS_DURATION".to_string(), track.duration.to_string()),
+        ("CMUS_POSITION".to_string(), track.position.to_string()),
+    ];
+    if let Some(stream) = &track.stream {
+        environment.push(("CMUS_STREAM".to_string(), stream.clone()));
+    }
+    environment.extend(
+        track
+            .metadata
+            .iter()
+            .map(|(key, value)| (format!("CMUS_{}", key.to_uppercase()), value.to_string())),
+    );
+    environment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn track() -> Track {
+        Track::from_str(include_str!(
+            "../tests/samples/cmus-remote-output-with-all-tags.txt"
+        ))
+        .unwrap()
+    }
+
+    fn commands(definitions: &[&str]) -> ExecCommands {
+        let definitions = definitions.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+        ExecCommands::new(&definitions, Duration::from_millis(500))
+    }
+
+    #[test]
+    fn test_run_the_command_with_the_track_environment() {
+        let commands = commands(&["hello=echo \"$CMUS_TITLE by $CMUS_ARTIST\""]);
+
+        assert_eq!(
+            commands.value("hello", &track()),
+            Some("Photograph by Alex Goot".to_string())
+        );
+        assert_eq!(commands.value("unknown", &track()), None);
+    }
+
+    #[test]
+    fn test_kill_the_command_after_the_timeout() {
+        let commands = commands(&["slow=exec sleep 5", "failing=exit 1"]);
+
+        let start = std::time::Instant::now();
+        assert_eq!(commands.value("slow", &track()), None);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(commands.value("failing", &track()), None);
+    }
+
+    #[test]
+    fn test_share_the_timeout_with_the_output() {
+        // The shell exits in time, but its background job keeps the output open.
+        let commands = commands(&["leaking=sleep 0.3; sleep 5 & echo early"]);
+
+        let start = std::time::Instant::now();
+        assert_eq!(commands.value("leaking", &track()), None);
+        assert!(start.elapsed() < Duration::from_millis(750));
+    }
+
+    #[test]
+    fn test_cache_the_output_per_track() {
+        let counter_file = std::env::temp_dir().join(format!("cmus-notify-exec-{}", std::process::id()));
+        let _ = std::fs::remove_file(&counter_file);
+        let counter = format!(
+            "counter=echo x >> '{0}'; wc -l < '{0}'",
+            counter_file.display()
+        );
+        let commands = commands(&[&counter]);
+
+        let mut track = track();
+        assert_eq!(commands.value("counter", &track).as_deref().map(str::trim), Some("1"));
+        track.position += 1;
+        assert_eq!(commands.value("counter", &track).as_deref().map(str::trim), Some("1"));
+        track.path.push_str(".other");
+        assert_eq!(commands.value("counter", &track).as_deref().map(str::trim), Some("2"));
+
+        let _ = std::fs::remove_file(&counter_file);
+    }
+
+    #[test]
+    fn test_reject_the_invalid_definitions() {
+        assert_eq!(parse_definition("rating=echo 5"), Ok(("rating", "echo 5")));
+        assert!(parse_definition("echo 5").is_err());
+        assert!(parse_definition("=echo 5").is_err());
+    }
+}
@@ -4,18 +4,32 @@ use log::{debug, info};
 use std::path::Path;
 
 pub mod cmus;
+pub mod cover_cache;
+pub mod cover_resize;
+pub mod embedded_art;
+pub mod exec;
 pub mod notification;
+pub mod path_template;
+pub mod progress_bar;
+pub mod scheduler;
 pub mod settings;
+pub mod status_display;
+pub mod template;
 
-/// Extracts the first embedded picture from an ID3 tag of an Audio file.
+/// Extracts the embedded cover from the tags of an Audio file.
+///
+/// The ID3 tags, the FLAC pictures, the Vorbis comments (FLAC, Ogg Opus and Vorbis), the MP4 "covr" atom,
+/// and the APEv2 tags (e.g. WavPack) are supported, see `embedded_art::read_pictures`.
 ///
 /// # Arguments
 ///
 /// * `track_path` - The path to the Audio file.
+/// * `picture_types` - The picture types to prefer, in order, `None` matches any type (see `embedded_art::select_picture`).
+/// * `min_size` - The minimum width and height of the picture, in pixels.
 ///
 /// # Returns
 ///
-/// Returns a `Result` containing a `TempFile` object with the contents of the extracted picture, or `None` if the MP3 file doesn't have any embedded pictures.
+/// Returns a `Result` containing a `DynamicImage` object with the contents of the extracted picture, or `None` if the file doesn't have any suitable embedded picture.
 /// In case of error, the `Result` will contain an error value of type `std::io::Error`.
 ///
 /// # Example
@@ -23,7 +37,7 @@ pub mod settings;
 /// ```ignore
 /// # use image::GenericImageView;
 /// # use cmus_notify::get_embedded_art;
-/// let result = get_embedded_art("/path/to/track.mp3");
+/// let result = get_embedded_art("/path/to/track.flac", &[Some(3), None], 64);
 ///
 /// match result {
 ///     Ok(Some(dynamic_image)) => {
@@ -34,13 +48,13 @@ pub mod settings;
 ///     Err(error) => println!("Error: {}", error),
 /// }
 /// ```
-pub fn get_embedded_art(track_path: &str) -> std::io::Result<Option<image::DynamicImage>> {
-    let tags = id3::Tag::read_from_path(track_path)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-    let Some(picture) = tags.pictures().next() else { return Ok(None); };
-    Ok(Some(image::load_from_memory(&picture.data).map_err(
-        |e| std::io::Error::new(std::io::ErrorKind::Other, e),
-    )?))
+pub fn get_embedded_art(
+    track_path: &str,
+    picture_types: &[Option<u8>],
+    min_size: u32,
+) -> std::io::Result<Option<image::DynamicImage>> {
+    let pictures = embedded_art::read_pictures(track_path)?;
+    Ok(embedded_art::select_picture(&pictures, picture_types, min_size))
 }
 
 /// Searches for a file that matches the provided regular expression in the specified search directory and its subdirectories.
@@ -113,7 +127,7 @@ pub fn search_for(
 }
 
 pub struct CompleteStr {
-    pub template: String,
+    pub template: std::rc::Rc<template::Template>,
     pub str: String
 }
 
@@ -127,21 +141,39 @@ pub enum TrackCover {
     /// The cover is an external file.
     /// The `String` contains the absolute path of the external file.
     External(String),
+    /// The embedded cover, resized and stored in the cover cache.
+    /// The `String` contains the path of the cached PNG file.
+    Cached(String),
     /// The track does not have a cover.
     None,
 }
 
 impl TrackCover {
-    pub fn set_notification_image(&self, notification: &mut notify_rust::Notification) {
+    /// Set the cover as the notification image, resized first if it's too large (or not a square, if asked).
+    /// The cached covers are already resized.
+    pub fn set_notification_image(
+        &self,
+        notification: &mut notify_rust::Notification,
+        resize: &cover_resize::CoverResize,
+    ) {
         use TrackCover::*;
         match self {
             Embedded(cover) => {
                 #[cfg(feature = "debug")]
                 debug!("Setting the cover as the notification image.");
-                let Ok(image) = notify_rust::Image::try_from(cover.clone()) else { return; };
+                let Ok(image) = notify_rust::Image::try_from(resize.apply(cover.clone())) else { return; };
                 notification.image_data(image);
             }
             External(path) => {
+                #[cfg(feature = "debug")]
+                debug!("Setting the cover as the notification image.");
+                match resize.apply_to_file(path).map(notify_rust::Image::try_from) {
+                    Some(Ok(image)) => notification.image_data(image),
+                    // The cover is small enough, or we can't read it, let the server handle it.
+                    _ => notification.image_path(path),
+                };
+            }
+            Cached(path) => {
                 #[cfg(feature = "debug")]
                 debug!("Setting the cover as the notification image.");
                 notification.image_path(path);
@@ -158,38 +190,49 @@ impl TrackCover {
 /// If the track has an embedded cover, and `force_use_external_cover` is `false`, the embedded cover will be returned.
 /// If the track does not have an embedded cover, and `no_use_external_cover` is `false`, the function will search for an external cover.
 /// If the track has an embedded cover, and `force_use_external_cover` is `true`, the function will search for an external cover.
+/// The embedded pictures that don't match `picture_types`, or are smaller than `min_size`, are not covers.
+///
+/// The external cover is the first image that matches the rendered `cover_path` glob pattern (see `path_template`),
+/// if it's given, and if nothing matches it, the cover is searched in the track's directory and its parents.
 #[inline]
+#[allow(clippy::too_many_arguments)]
 pub fn track_cover(
-    mut path: String,
+    path: &str,
+    cover_path: Option<&str>,
     track_name: &str,
     max_depth: u8,
     force_use_external_cover: bool,
     no_use_external_cover: bool,
+    picture_types: &[Option<u8>],
+    min_size: u32,
 ) -> TrackCover {
     if !force_use_external_cover {
         #[cfg(feature = "debug")]
         info!("Trying to get the embedded cover of \"{path}\".");
-        if let Ok(Some(cover)) = get_embedded_art(&path) {
+        if let Ok(Some(cover)) = get_embedded_art(path, picture_types, min_size) {
             return TrackCover::Embedded(cover);
         }
     }
 
     if !no_use_external_cover {
-        let (Ok(regx), path) = (match path.split('/').last() {
-            Some(last_pat) if last_pat.contains("r#") => {
-                (regex::Regex::new(&last_pat.replace("r#", "")),
-                // Remove the last part of the path
-                path.remove(path.len() - last_pat.len() - 1).to_string())
+        if let Some(cover_path) = cover_path {
+            #[cfg(feature = "debug")]
+            info!("Trying to get the external cover that matches \"{cover_path}\".");
+            if let Some(cover) = path_template::find_file(cover_path, path, path_template::COVER_EXTENSIONS) {
+                #[cfg(feature = "debug")]
+                info!("Found the external cover \"{cover}\".");
+                return TrackCover::External(cover);
             }
-            _ => (regex::Regex::new(&format!(r"({track_name}).*\.(jpg|jpeg|png|gif)$")), path),
-        }) else {
+        }
+
+        let Ok(regx) = regex::Regex::new(&format!(r"({track_name}).*\.(jpg|jpeg|png|gif)$")) else {
             #[cfg(feature = "debug")]
             info!("Could not get the cover.");
             return TrackCover::None;
         };
         #[cfg(feature = "debug")]
         info!("Trying to get the external cover of \"{path}\".");
-        if let Ok(Some(cover)) = search_for(&path, max_depth, &regx) {
+        if let Ok(Some(cover)) = search_for(path, max_depth, &regx) {
             #[cfg(feature = "debug")]
             info!("Found the external cover \"{cover}\".");
             return TrackCover::External(cover);
@@ -202,10 +245,19 @@ pub fn track_cover(
     TrackCover::None
 }
 
+/// Returns the entries of the directory sorted by their name, `read_dir` returns them in the file system order,
+/// so the first matching cover would change between the machines (e.g. "cover.jpg" or "cover.png").
+fn sorted_entries(directory: &str) -> std::io::Result<Vec<std::fs::DirEntry>> {
+    let mut entries = std::fs::read_dir(directory)?
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
 #[inline]
 fn search(search_directory: &str, matcher: &regex::Regex) -> std::io::Result<Option<String>> {
-    for entry in std::fs::read_dir(search_directory)? {
-        let Ok(entry) = entry else { continue; };
+    for entry in sorted_entries(search_directory)? {
         let Ok(file_type) = entry.file_type() else { continue; };
         if file_type.is_file() {
             let Ok(file_name) = entry.file_name().into_string() else { continue; };
@@ -220,40 +272,137 @@ fn search(search_directory: &str, matcher: &regex::Regex) -> std::io::Result<Opt
     Ok(None)
 }
 
+/// The prefix of the placeholders that refer to the state before the change, e.g. "{prev_title}".
+pub(crate) const PREVIOUS_STATE_PREFIX: &str = "prev_";
+
+/// The icons of the "{seek_icon}" placeholder, if the position moved forward or backward.
+const SEEK_FORWARD_ICON: &str = "⏩";
+const SEEK_BACKWARD_ICON: &str = "⏪";
+
+/// The placeholders that depend on the change, or on the settings, besides the track and the player settings ones.
+const STATE_CHANGE_PLACEHOLDERS: &[&str] = &["seek_icon", "progress_bar"];
+
+/// Returns true if the placeholder key is known, a track, a tag, a player setting, or a change placeholder,
+/// optionally prefixed with "prev_".
+pub fn is_known_placeholder(key: &str) -> bool {
+    let key = key.strip_prefix(PREVIOUS_STATE_PREFIX).unwrap_or(key);
+    [
+        STATE_CHANGE_PLACEHOLDERS,
+        Track::PLACEHOLDERS,
+        cmus::TrackMetadata::KNOWN_TAGS,
+        cmus::player_settings::PlayerSettings::PLACEHOLDERS,
+    ]
+    .iter()
+    .any(|placeholders| placeholders.contains(&key))
+}
+
+/// The values of a change, the state before it and the state after it.
+struct StateChange<'a> {
+    before: &'a cmus::events::Snapshot,
+    after: &'a cmus::events::Snapshot,
+    settings: &'a settings::Settings,
+}
+
+impl TemplateProcessor for StateChange<'_> {
+    fn value(&self, key: &str) -> Option<String> {
+        let (snapshot, key) = match key.strip_prefix(PREVIOUS_STATE_PREFIX) {
+            Some(key) => (self.before, key),
+            None => (self.after, key),
+        };
+        if let Some(name) = key.strip_prefix(exec::EXEC_PREFIX) {
+            return self.settings.exec_commands().value(name, &snapshot.track);
+        }
+        match key {
+            "seek_icon" if self.after.track.position >= self.before.track.position => {
+                Some(SEEK_FORWARD_ICON.to_string())
+            }
+            "seek_icon" => Some(SEEK_BACKWARD_ICON.to_string()),
+            "progress_bar" => Some(progress_bar::render(
+                snapshot.track.position,
+                snapshot.track.duration,
+                self.settings,
+            )),
+            "progress" | "position" | "duration" | "remaining" | "percent" => snapshot
+                .track
+                .time_value(key, self.settings.time_format()),
+            _ => snapshot.value(key),
+        }
+    }
+}
+
 /// Replace all the placeholders in the template with their matching value.
+///
+/// The placeholders prefixed with "prev_" (e.g. "{prev_volume}") are replaced with the values
+/// of the state before the change, and the rest with the values of the state after it.
+/// The "{seek_icon}" placeholder is replaced with an icon that tells if the position moved forward or backward,
+/// and the "{progress_bar}" and the time placeholders (e.g. "{position}") are rendered as the settings say.
+/// The "{exec:NAME}" placeholders are replaced with the output of the configured commands.
+///
+/// If the template is a markup (e.g. the notification body), the values will be escaped.
 #[inline(always)]
 pub fn process_template_placeholders(
     template: String,
-    track: &Track,
-    player_settings: &cmus::player_settings::PlayerSettings,
+    before: &cmus::events::Snapshot,
+    after: &cmus::events::Snapshot,
+    settings: &settings::Settings,
+    markup: bool,
 ) -> String {
-    let res = track.process(template);
-    player_settings.process(res)
+    let mut out = String::new();
+    render_template(
+        &template::Template::compile(&template),
+        before,
+        after,
+        settings,
+        markup,
+        &mut out,
+    );
+    out
+}
+
+/// Like `process_template_placeholders`, but with a parsed template, into a reusable buffer.
+#[inline(always)]
+pub fn render_template(
+    template: &template::Template,
+    before: &cmus::events::Snapshot,
+    after: &cmus::events::Snapshot,
+    settings: &settings::Settings,
+    markup: bool,
+    out: &mut String,
+) {
+    let change = StateChange {
+        before,
+        after,
+        settings,
+    };
+    template.render_into(&change, markup, out);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cmus::events::Snapshot;
     use crate::cmus::player_settings::PlayerSettings;
+    use crate::settings::Settings;
     use std::str::FromStr;
     use test_context::{test_context, TestContext};
 
     struct TestContextWithFullTrack {
-        track: Track,
-        player_settings: PlayerSettings,
+        snapshot: Snapshot,
     }
 
     impl TestContext for TestContextWithFullTrack {
         fn setup() -> Self {
             Self {
-                track: cmus::Track::from_str(include_str!(
-                    "../tests/samples/cmus-remote-output-with-all-tags.txt"
-                ))
-                .unwrap(),
-                player_settings: PlayerSettings::from_str(include_str!(
-                    "../tests/samples/player_settings_mode-artist_vol-46_repeat-false_repeat_current-false_shuffle-tracks.txt"
-                ))
-                .unwrap(),
+                snapshot: Snapshot::new(
+                    cmus::Track::from_str(include_str!(
+                        "../tests/samples/cmus-remote-output-with-all-tags.txt"
+                    ))
+                    .unwrap(),
+                    PlayerSettings::from_str(include_str!(
+                        "../tests/samples/player_settings_mode-artist_vol-46_repeat-false_repeat_current-false_shuffle-tracks.txt"
+                    ))
+                    .unwrap(),
+                ),
             }
         }
     }
@@ -263,7 +412,13 @@ mod tests {
     fn test_process_path_template(ctx: &TestContextWithFullTrack) {
         let cover_path_template = String::from("{title}/{artist}/{album}/{tracknumber}");
         let cover_path =
-            process_template_placeholders(cover_path_template, &ctx.track, &ctx.player_settings);
+            process_template_placeholders(
+            cover_path_template,
+            &ctx.snapshot,
+            &ctx.snapshot,
+            &Settings::default(),
+            false,
+        );
 
         assert_eq!(
             cover_path,
@@ -271,6 +426,31 @@ mod tests {
         );
     }
 
+    #[test_context(TestContextWithFullTrack)]
+    #[test]
+    fn test_process_the_previous_state_placeholders(ctx: &TestContextWithFullTrack) {
+        let mut before = ctx.snapshot.clone();
+        before.player_settings.volume.left = 40;
+        before.player_settings.volume.right = 40;
+        before.track = cmus::Track::from_str(include_str!(
+            "../tests/samples/row/cmus-remote-output-row.txt"
+        ))
+        .unwrap();
+
+        let body = process_template_placeholders(
+            String::from("Volume {prev_volume}% → {volume}%, skipped {prev_title}, now playing {title}"),
+            &before,
+            &ctx.snapshot,
+            &Settings::default(),
+            false,
+        );
+
+        assert_eq!(
+            body,
+            "Volume 40% → 46%, skipped Haunted, now playing Photograph"
+        );
+    }
+
     #[test]
     fn test_search_for_cover_with_the_cover_key_world() {
         let cover_path = search_for(
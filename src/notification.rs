@@ -3,11 +3,13 @@ use log::info;
 use notify_rust::Notification;
 
 use crate::{CompleteStr, track_cover, TrackCover};
-use crate::cmus::{TemplateProcessor, Track};
-use crate::cmus::events::CmusEvent;
-use crate::cmus::player_settings::PlayerSettings;
-use crate::cmus::query::CmusQueryResponse;
+use crate::cover_cache::CoverCache;
+use crate::path_template;
+use crate::cmus::Track;
+use crate::cmus::events::{CmusEvent, Snapshot};
 use crate::settings::Settings;
+use crate::template::Template;
+use std::rc::Rc;
 
 pub enum Action {
     Show {
@@ -21,25 +23,35 @@ pub enum Action {
 
 pub struct NotificationsHandler {
     cover_set: bool,
+    /// The path of the last track, and the tags in its path, so the filename patterns run once per track.
+    path_tags: Option<(String, Vec<(String, String)>)>,
+    cover_cache: Option<CoverCache>,
     notification: Notification,
     notifications: Vec<CmusNotification>,
     settings: Settings,
 }
 
 struct CmusNotification {
-    body_template: String,
-    summary_template: String,
+    body_template: Rc<Template>,
+    summary_template: Rc<Template>,
+    /// The state before the event that showed the notification, the "prev_" placeholders always refer to it.
+    before: Snapshot,
+    /// A reusable buffer to render the templates into, on every update.
+    buffer: String,
     visible: bool,
     handle: notify_rust::NotificationHandle
 }
 
 impl CmusNotification {
     #[inline(always)]
-    fn update(&mut self, track: &Track, player_settings: &PlayerSettings) {
-        use crate::process_template_placeholders;
-        self.handle.summary(&process_template_placeholders(self.summary_template.clone(), track, player_settings))
-            .body(&process_template_placeholders(self.body_template.clone(), track, player_settings));
-        self.handle.update();
+    fn update(&mut self, after: &Snapshot, settings: &Settings) {
+        use crate::render_template;
+        // The body supports the markup, but the summary doesn't.
+        render_template(&self.summary_template, &self.before, after, settings, false, &mut self.buffer);
+        self.handle.summary(&self.buffer);
+        render_template(&self.body_template, &self.before, after, settings, true, &mut self.buffer);
+        self.handle.body(&self.buffer);
+        let _ = self.handle.update();
     }
 }
 
@@ -47,33 +59,49 @@ impl NotificationsHandler {
     pub fn new(settings: Settings) -> Self {
         Self {
             cover_set: false,
+            path_tags: None,
+            cover_cache: CoverCache::from_settings(&settings),
             notification: Notification::new(),
             notifications: Vec::with_capacity(2),
             settings,
         }
     }
 
+    /// Returns true if there's a visible notification that needs to be updated while the track is playing,
+    /// e.g. one that shows the progress.
+    #[inline(always)]
+    pub fn has_live_notifications(&self) -> bool {
+        self.notifications.iter().any(|notification| notification.visible)
+    }
+
     #[inline]
     pub fn show_notification(
         &mut self,
         events: Vec<CmusEvent>,
-        response: &CmusQueryResponse,
     ) -> Result<(), notify_rust::error::Error> {
-        for event in events {
+        for mut event in events {
+            // Fill the missing tags of the untagged tracks, before anything uses them.
+            // The state before the change first, it's usually the last track.
+            if !self.settings.filename_patterns().is_empty() {
+                let (before, after) = event.states_mut();
+                self.fill_path_tags(&mut before.track);
+                self.fill_path_tags(&mut after.track);
+            }
+
             #[cfg(feature = "debug")]
             info!("event: {:?}", event);
 
-            if let CmusEvent::PositionChanged(track, player_settings) = &event {
+            if let CmusEvent::PositionChanged(_, after) = &event {
                 for notification in &mut self.notifications {
                     if notification.visible {
-                        notification.update(track, player_settings);
+                        notification.update(after, &self.settings);
                     }
                 }
                 continue;
-            } else if let CmusEvent::TrackChanged(_, _) = &event {
+            } else if let CmusEvent::TrackChanged(_, _) | CmusEvent::Disconnected(_, _) = &event {
                 for notification in &mut self.notifications {
                     notification.handle.timeout = 2.into(); // Hide the notification after 2 millisecond
-                    notification.handle.update();
+                    let _ = notification.handle.update();
                 }
                 // Clean the notifications vec
                 self.notifications.clear();
@@ -83,7 +111,7 @@ impl NotificationsHandler {
                 Action::Show { body, summary, timeout, save } => {
                     // Setup the notification cover
                     if self.settings.show_track_cover {
-                        self.update_cover(&event, response);
+                        self.update_cover(&event);
                     } else if self.settings.notification_static_cover.is_some() && !self.cover_set {
                         self.setup_the_notification();
                         self.notification
@@ -94,7 +122,7 @@ impl NotificationsHandler {
                     self.notification.timeout(timeout).summary(&summary.str).body(&body.str);
 
                     // Show the notification
-                    let mut handle = self.notification.show()?;
+                    let handle = self.notification.show()?;
                     if save {
                         // Add the close handler
                         /*handle.on_close(|reason| {
@@ -105,6 +133,8 @@ impl NotificationsHandler {
                             CmusNotification {
                                 body_template: body.template,
                                 summary_template: summary.template,
+                                before: event.states().0.clone(),
+                                buffer: String::new(),
                                 visible: true,
                                 handle
                             }
@@ -118,21 +148,36 @@ impl NotificationsHandler {
         Ok(())
     }
 
+    /// Fill the missing tags of the track from its path, the patterns run only when the track changes.
+    fn fill_path_tags(&mut self, track: &mut Track) {
+        if self.path_tags.as_ref().map_or(true, |(path, _)| *path != track.path) {
+            let tags = track.path_tags(self.settings.filename_patterns());
+            self.path_tags = Some((track.path.clone(), tags));
+        }
+        if let Some((_, tags)) = &self.path_tags {
+            track.fill_metadata(tags);
+        }
+    }
+
     #[inline(always)]
-    fn update_cover(&mut self, event: &CmusEvent, response: &CmusQueryResponse) {
+    fn update_cover(&mut self, event: &CmusEvent) {
         // If the track is changed, we need to update the cover.
         match event {
-            CmusEvent::TrackChanged(track, _) => {
+            CmusEvent::TrackChanged(_, after) => {
                 // Reset the notification
                 self.setup_the_notification();
-                self.set_cover(track);
+                self.set_cover(&after.track);
+            }
+            // The track is gone, so the cover is the last track's one.
+            CmusEvent::Disconnected(before, _) => {
+                if !self.cover_set {
+                    self.set_cover(&before.track);
+                }
             }
             _ => {
                 if !self.cover_set {
                     // If the cover is not found, we need to update it.
-                    if let Ok(track) = response.track() {
-                        self.set_cover(&track);
-                    }
+                    self.set_cover(&event.states().1.track);
                 }
             }
         };
@@ -140,21 +185,10 @@ impl NotificationsHandler {
 
     #[inline]
     fn set_cover(&mut self, track: &Track) {
-        let path = match &self.settings.cover_path_template {
-            Some(template) => track.process(template.clone()),
-            None => track.path.clone(),
-        };
-        // Get the track cover and set it to notification
-        let track_cover = track_cover(
-            path,
-            track.get_name(),
-            self.settings.depth(),
-            self.settings.force_use_external_cover,
-            self.settings.no_use_external_cover,
-        );
+        let track_cover = self.resolve_cover(track);
 
         if track_cover != TrackCover::None {
-            track_cover.set_notification_image(&mut self.notification);
+            track_cover.set_notification_image(&mut self.notification, &self.settings.cover_resize());
         } else if self.settings.notification_static_cover.is_some() {
             self.notification
                 .image_path(self.settings.notification_static_cover.as_ref().unwrap());
@@ -164,6 +198,62 @@ impl NotificationsHandler {
         self.cover_set = true;
     }
 
+    /// Get the embedded cover of the track, or search for the external one, through the cache if it's enabled.
+    /// The tracks of the same album share the cached embedded cover.
+    #[inline]
+    fn resolve_cover(&self, track: &Track) -> TrackCover {
+        let cover_path = self
+            .settings
+            .cover_path_template
+            .is_some()
+            .then(|| path_template::render(&self.settings.template("cover_path_template"), track));
+        let Some(cache) = &self.cover_cache else {
+            return self.find_cover(
+                track,
+                cover_path.as_deref(),
+                !self.settings.force_use_external_cover,
+                !self.settings.no_use_external_cover,
+            );
+        };
+
+        if !self.settings.force_use_external_cover {
+            let key = cache.embedded_key(track);
+            if let Some(cover) = cache.get(&key) {
+                return cover;
+            }
+            let cover = self.find_cover(track, None, true, false);
+            if cover != TrackCover::None {
+                return cache.store(&key, cover, &track.path);
+            }
+        }
+        if !self.settings.no_use_external_cover {
+            let key = cache.external_key(track, cover_path.as_deref());
+            if let Some(cover) = cache.get(&key) {
+                return cover;
+            }
+            let cover = self.find_cover(track, cover_path.as_deref(), false, true);
+            if cover != TrackCover::None {
+                return cache.store(&key, cover, &track.path);
+            }
+        }
+        TrackCover::None
+    }
+
+    /// Get the embedded cover of the track, and (or) search for the external one.
+    #[inline]
+    fn find_cover(&self, track: &Track, cover_path: Option<&str>, embedded: bool, external: bool) -> TrackCover {
+        track_cover(
+            &track.path,
+            cover_path,
+            track.get_name(),
+            self.settings.depth(),
+            !embedded,
+            !external,
+            &self.settings.cover_picture_types(),
+            self.settings.cover_min_size(),
+        )
+    }
+
     #[inline(always)]
     fn setup_the_notification(&mut self) {
         self.notification = Notification::new();
@@ -0,0 +1,131 @@
+//! Resolve the cover path template to the files, e.g. "covers/{artist}/{album}/cover.*".
+//!
+//! The rendered template is a glob pattern (`*`, `?`, `[abc]` and `**`), a leading `~` is the home directory,
+//! and a relative path starts from the track's directory.
+use crate::cmus::{TemplateProcessor, Track};
+use crate::template::Template;
+#[cfg(feature = "debug")]
+use log::debug;
+use std::path::Path;
+
+/// The extensions of the cover files.
+pub const COVER_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp"];
+
+/// The track placeholders, escaped, so a tag like "[Live]" doesn't turn into a pattern.
+struct GlobEscaped<'a>(&'a Track);
+
+impl TemplateProcessor for GlobEscaped<'_> {
+    fn value(&self, key: &str) -> Option<String> {
+        self.0.value(key).map(|value| glob::Pattern::escape(&value))
+    }
+}
+
+/// Render the path template with the track placeholders, the values are escaped for the glob.
+pub fn render(template: &Template, track: &Track) -> String {
+    template.render(&GlobEscaped(track))
+}
+
+/// Expand the "~" to the home directory, and start the relative pattern from the track's directory.
+fn absolute_pattern(pattern: &str, track_path: &str) -> String {
+    if pattern == "~" || pattern.starts_with("~/") {
+        if let Some(home) = std::env::var_os("HOME") {
+            return format!(
+                "{}{}",
+                glob::Pattern::escape(&home.to_string_lossy()),
+                &pattern[1..]
+            );
+        }
+    }
+    if Path::new(pattern).is_absolute() {
+        return pattern.to_string();
+    }
+    match Path::new(track_path).parent().and_then(Path::to_str) {
+        Some(directory) if !directory.is_empty() => {
+            format!("{}/{pattern}", glob::Pattern::escape(directory))
+        }
+        _ => pattern.to_string(),
+    }
+}
+
+/// Returns the first file (in the alphabetical order) that matches the rendered path template,
+/// and has one of the extensions (in any case).
+pub fn find_file(pattern: &str, track_path: &str, extensions: &[&str]) -> Option<String> {
+    let pattern = absolute_pattern(pattern, track_path);
+    #[cfg(feature = "debug")]
+    debug!("Searching for a file that matches \"{pattern}\".");
+
+    let options = glob::MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: true,
+    };
+    glob::glob_with(&pattern, options)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|path| path.is_file())
+        .find(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    extensions
+                        .iter()
+                        .any(|wanted| wanted.eq_ignore_ascii_case(extension))
+                })
+        })
+        .and_then(|path| path.to_str().map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACK_PATH: &str = "tests/samples/Owl City/Cinematic/08 - Always.mp3";
+
+    #[test]
+    fn test_find_the_file_relative_to_the_track_directory() {
+        assert_eq!(
+            find_file("cover/*", TRACK_PATH, COVER_EXTENSIONS),
+            Some("tests/samples/Owl City/Cinematic/cover/cover.jpg".to_string())
+        );
+        assert_eq!(
+            find_file("cover/cover.[pg]*", TRACK_PATH, COVER_EXTENSIONS),
+            Some("tests/samples/Owl City/Cinematic/cover/cover.png".to_string())
+        );
+        assert_eq!(
+            find_file("**/*", TRACK_PATH, &["lrc"]),
+            Some("tests/samples/Owl City/Cinematic/08 - Always.lrc".to_string())
+        );
+        assert_eq!(find_file("*", TRACK_PATH, COVER_EXTENSIONS), None);
+    }
+
+    #[test]
+    fn test_escape_the_placeholder_values() {
+        let mut track = Track {
+            path: TRACK_PATH.to_string(),
+            ..Track::default()
+        };
+        track.metadata.set_if_missing("album", "Cinematic [Live]");
+        let template = Template::compile("covers/{album}/*.jpg");
+
+        assert_eq!(
+            render(&template, &track),
+            "covers/Cinematic [[]Live[]]/*.jpg"
+        );
+    }
+
+    #[test]
+    fn test_expand_the_home_directory() {
+        let Some(home) = std::env::var_os("HOME") else {
+            return;
+        };
+
+        assert_eq!(
+            absolute_pattern("~/covers/*.jpg", TRACK_PATH),
+            format!("{}/covers/*.jpg", home.to_string_lossy())
+        );
+        assert_eq!(
+            absolute_pattern("/covers/*.jpg", TRACK_PATH),
+            "/covers/*.jpg"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use crate::settings::Settings;
+
+/// Render the "{progress_bar}" placeholder, e.g. "━━━━●─────── 35%".
+///
+/// The bar is `width` characters long, the played part is filled with the fill character,
+/// followed by the head character (if any), and the rest is filled with the empty character.
+/// If the duration is unknown (e.g. a stream), the bar is empty, without the percentage.
+pub fn render(position: u32, duration: u32, settings: &Settings) -> String {
+    let width = settings.progress_bar_width() as usize;
+    let head = settings.progress_bar_head();
+    let ratio = if duration > 0 {
+        (position as f64 / duration as f64).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    let mut filled = (ratio * width as f64).round() as usize;
+    let head_width = if head.is_empty() || width == 0 { 0 } else { 1 };
+    // Keep a place for the head, unless the track is finished.
+    if filled + head_width > width {
+        filled = width - head_width;
+    }
+    let empty = width - filled - head_width;
+
+    let mut bar = settings.progress_bar_fill().repeat(filled);
+    if head_width > 0 {
+        bar.push_str(head);
+    }
+    bar.push_str(&settings.progress_bar_empty().repeat(empty));
+
+    if settings.progress_bar_percentage && duration > 0 {
+        bar.push_str(&format!(" {}%", (ratio * 100.0).round()));
+    }
+    bar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn settings(args: &[&str]) -> Settings {
+        Settings::parse_from([&["cmus-notify"], args].concat())
+    }
+
+    #[test]
+    fn test_render_the_progress_bar() {
+        let settings = settings(&["--progress-bar-width", "10"]);
+
+        assert_eq!(render(0, 100, &settings), "●─────────");
+        assert_eq!(render(35, 100, &settings), "━━━━●─────");
+        assert_eq!(render(100, 100, &settings), "━━━━━━━━━●");
+    }
+
+    #[test]
+    fn test_render_the_progress_bar_with_the_custom_glyphs_and_the_percentage() {
+        let settings = settings(&[
+            "--progress-bar-width",
+            "4",
+            "--progress-bar-fill",
+            "#",
+            "--progress-bar-empty",
+            ".",
+            "--progress-bar-head",
+            "",
+            "--progress-bar-percentage",
+        ]);
+
+        assert_eq!(render(50, 100, &settings), "##.. 50%");
+        assert_eq!(render(10, 0, &settings), "....");
+    }
+}
@@ -0,0 +1,230 @@
+use crate::cmus::events::CmusEvent;
+use crate::cmus::query::CmusQueryResponse;
+use crate::cmus::TrackStatus;
+#[cfg(feature = "debug")]
+use log::debug;
+
+/// The interval used right after a change, or while a live notification is visible, in milliseconds.
+const FAST_INTERVAL_TIME: u64 = 1000;
+/// The maximum interval used while cmus isn't playing, in milliseconds.
+const MAX_IDLE_INTERVAL_TIME: u64 = 8000;
+/// The maximum interval between the attempts to reach cmus, while it's not running, in milliseconds.
+const MAX_RECONNECT_INTERVAL_TIME: u64 = 16000;
+/// How long we wait after the track end, to make sure cmus moved to the next track, in milliseconds.
+const TRACK_END_MARGIN: u64 = 500;
+
+/// Decides how long the main loop should sleep before the next query.
+///
+/// With a non-zero interval, it's always the interval.
+/// With a zero interval, it depends on the player state:
+/// * While playing, we wake up when the track ends (`duration - position`).
+/// * While paused or stopped, we poll with an interval that starts fast,
+///   and doubles after every quiet query, up to a few seconds.
+/// * After a change (e.g. the user skipped the track), we poll fast again, because more changes usually follow.
+/// * While a live notification is visible (e.g. "{progress}"), we poll fast, to keep it up to date.
+///
+/// And while cmus is not running, we try to reach it with an exponential back off, whatever the interval is.
+pub struct Scheduler {
+    interval: u64,
+    backoff: u64,
+    reconnect_attempts: u32,
+}
+
+/// Returns true if the events change something the user can see, so more changes may follow.
+/// The position moves on every query while playing, so a `PositionChanged` event alone is not a change,
+/// the live notifications that show the position are kept up to date by the `live` flag instead.
+pub fn is_change(events: &[CmusEvent]) -> bool {
+    events
+        .iter()
+        .any(|event| !matches!(event, CmusEvent::PositionChanged(..)))
+}
+
+impl Scheduler {
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval,
+            backoff: FAST_INTERVAL_TIME,
+            reconnect_attempts: 0,
+        }
+    }
+
+    /// Returns true if the interval depends on the track duration (the interval is zero).
+    #[inline(always)]
+    pub fn is_adaptive(&self) -> bool {
+        self.interval == 0
+    }
+
+    /// Returns the time to sleep before the next query, in milliseconds.
+    ///
+    /// * `response` - The last response from cmus.
+    /// * `changed` - Whether the last query produced any change, see `is_change`.
+    /// * `live` - Whether there's a visible notification that needs to be updated.
+    pub fn next_wake_up(&mut self, response: &CmusQueryResponse, changed: bool, live: bool) -> u64 {
+        self.reconnect_attempts = 0;
+        if !self.is_adaptive() {
+            return self.interval;
+        }
+
+        if changed {
+            self.backoff = FAST_INTERVAL_TIME;
+        }
+        let backoff = self.backoff;
+        // The next quiet query will wait longer.
+        self.backoff = self.backoff.saturating_mul(2);
+
+        if live {
+            return FAST_INTERVAL_TIME;
+        }
+
+        let wake_up = match response.track() {
+            // If we don't know the duration (e.g. a stream), we can't know when the track ends.
+            Ok(track) if track.status == TrackStatus::Playing && track.duration > 0 => {
+                let remaining = track.duration.saturating_sub(track.position) as u64 * 1000;
+                backoff.min(remaining + TRACK_END_MARGIN)
+            }
+            _ => backoff.min(MAX_IDLE_INTERVAL_TIME),
+        };
+
+        #[cfg(feature = "debug")]
+        debug!("The next wake up is after {wake_up} ms.");
+
+        wake_up
+    }
+
+    /// Returns the time to sleep before the next attempt to reach cmus, in milliseconds.
+    /// The time doubles after every failed attempt, up to a few seconds (or the interval, if it's longer).
+    pub fn next_reconnect(&mut self) -> u64 {
+        let base = if self.is_adaptive() {
+            FAST_INTERVAL_TIME
+        } else {
+            self.interval
+        };
+        let wake_up = base
+            .saturating_mul(2u64.saturating_pow(self.reconnect_attempts))
+            .min(MAX_RECONNECT_INTERVAL_TIME.max(base));
+        self.reconnect_attempts = self.reconnect_attempts.saturating_add(1);
+
+        #[cfg(feature = "debug")]
+        debug!("The next attempt to reach cmus is after {wake_up} ms.");
+
+        wake_up
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn response(status: &str, duration: u32, position: u32) -> CmusQueryResponse {
+        CmusQueryResponse::from_str(&format!(
+            "status {status}\nfile /a.mp3\nduration {duration}\nposition {position}\nset repeat false\n"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_use_the_fixed_interval_if_not_zero() {
+        let mut scheduler = Scheduler::new(1500);
+
+        assert_eq!(
+            scheduler.next_wake_up(&response("playing", 240, 10), false, false),
+            1500
+        );
+        assert_eq!(
+            scheduler.next_wake_up(&CmusQueryResponse::default(), true, true),
+            1500
+        );
+    }
+
+    #[test]
+    fn test_sleep_until_the_track_ends_while_playing() {
+        let mut scheduler = Scheduler::new(0);
+        let playing = response("playing", 240, 10);
+
+        // Right after a change, poll fast, then back off until we reach the track end.
+        assert_eq!(scheduler.next_wake_up(&playing, true, false), 1000);
+        assert_eq!(scheduler.next_wake_up(&playing, false, false), 2000);
+        for _ in 0..10 {
+            scheduler.next_wake_up(&playing, false, false);
+        }
+        assert_eq!(scheduler.next_wake_up(&playing, false, false), 230_500);
+    }
+
+    #[test]
+    fn test_sleep_until_the_track_ends_while_the_position_moves() {
+        let mut scheduler = Scheduler::new(0);
+        let mut previous = response("playing", 240, 10);
+        let mut position = 10;
+        let mut wake_up = scheduler.next_wake_up(&previous, true, false);
+
+        // Every query finds the position moved by the time we slept, and nothing else.
+        for _ in 0..7 {
+            position += wake_up as u32 / 1000;
+            let next = response("playing", 240, position);
+            let events = previous
+                .events(&next, std::time::Duration::from_millis(wake_up))
+                .unwrap();
+            assert!(matches!(events[..], [CmusEvent::PositionChanged(..)]));
+
+            wake_up = scheduler.next_wake_up(&next, is_change(&events), false);
+            previous = next;
+        }
+        assert_eq!(position, 137);
+        assert_eq!(wake_up, 103_500);
+    }
+
+    #[test]
+    fn test_count_the_other_events_as_a_change() {
+        let playing = response("playing", 240, 10);
+        let paused = response("paused", 240, 11);
+        let events = playing
+            .events(&paused, std::time::Duration::from_secs(1))
+            .unwrap();
+
+        assert!(is_change(&events));
+        assert!(!is_change(&[]));
+    }
+
+    #[test]
+    fn test_poll_with_a_capped_interval_while_paused() {
+        let mut scheduler = Scheduler::new(0);
+        let paused = response("paused", 240, 10);
+
+        assert_eq!(scheduler.next_wake_up(&paused, false, false), 1000);
+        for _ in 0..10 {
+            scheduler.next_wake_up(&paused, false, false);
+        }
+        assert_eq!(scheduler.next_wake_up(&paused, false, false), 8000);
+        // A change resets the back off.
+        assert_eq!(scheduler.next_wake_up(&paused, true, false), 1000);
+    }
+
+    #[test]
+    fn test_back_off_while_cmus_is_not_running() {
+        let mut scheduler = Scheduler::new(500);
+
+        assert_eq!(scheduler.next_reconnect(), 500);
+        assert_eq!(scheduler.next_reconnect(), 1000);
+        assert_eq!(scheduler.next_reconnect(), 2000);
+        for _ in 0..10 {
+            scheduler.next_reconnect();
+        }
+        assert_eq!(scheduler.next_reconnect(), 16000);
+
+        // Once cmus is back, the back off starts over.
+        scheduler.next_wake_up(&response("playing", 240, 10), true, false);
+        assert_eq!(scheduler.next_reconnect(), 500);
+    }
+
+    #[test]
+    fn test_poll_fast_while_a_live_notification_is_visible() {
+        let mut scheduler = Scheduler::new(0);
+        let playing = response("playing", 240, 10);
+
+        for _ in 0..10 {
+            scheduler.next_wake_up(&playing, false, false);
+        }
+        assert_eq!(scheduler.next_wake_up(&playing, false, true), 1000);
+    }
+}
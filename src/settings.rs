@@ -1,7 +1,17 @@
 use clap::Parser;
 #[cfg(feature = "debug")]
 use log::{debug, info};
+use crate::cmus::filename_pattern::FilenamePattern;
+use crate::cmus::TrackMetadata;
+use crate::cover_resize::{self, CoverResize};
+use crate::embedded_art;
+use crate::exec::{self, ExecCommands};
+use crate::template::Template;
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::str::FromStr;
 
 const NOTIFICATION_TIMEOUT: i32 = 5;
 const NOTIFICATION_BODY: &str =
@@ -10,7 +20,17 @@ const NOTIFICATION_SUMMARY: &str = "{title}";
 const NOTIFICATION_APP_NAME: &str = "C* Music Player";
 const DEFAULT_REMOTE_COMMAND: &str = "cmus-remote";
 const DEFAULT_MAX_DEPTH: u8 = 3;
+const DEFAULT_COVER_PICTURE_TYPES: &[&str] = &["front", "media", "any"];
+const DEFAULT_COVER_MIN_SIZE: u32 = 0; // 0 px, every picture is accepted
+const DEFAULT_COVER_MAX_SIZE: u32 = 256; // 256 px
+const DEFAULT_COVER_RESIZE_FILTER: &str = "triangle";
 const DEFAULT_INTERVAL_TIME: u64 = 1000; // 1000 ms
+const DEFAULT_LINK_GRACE_PERIOD: u64 = 3000; // 3000 ms
+const DEFAULT_EXEC_TIMEOUT: u64 = 1000; // 1000 ms
+const DEFAULT_PROGRESS_BAR_WIDTH: u16 = 20;
+const DEFAULT_PROGRESS_BAR_FILL: &str = "━";
+const DEFAULT_PROGRESS_BAR_EMPTY: &str = "─";
+const DEFAULT_PROGRESS_BAR_HEAD: &str = "●";
 const DEFAULT_STATUS_CHANGE_NOTIFICATION_BODY: &str = "<b>{status}</b>";
 const DEFAULT_STATUS_CHANGE_NOTIFICATION_SUMMARY: &str = "{title}";
 const DEFAULT_STATUS_CHANGE_NOTIFICATION_TIMEOUT: i32 = 1;
@@ -23,9 +43,39 @@ const DEFAULT_SHUFFLE_NOTIFICATION_TIMEOUT: i32 = 1;
 const DEFAULT_REPEAT_NOTIFICATION_BODY: &str = "Repeat mode changed to {repeat}";
 const DEFAULT_REPEAT_NOTIFICATION_SUMMARY: &str = "{title}";
 const DEFAULT_REPEAT_NOTIFICATION_TIMEOUT: i32 = 1;
+const DEFAULT_REPEAT_CURRENT_NOTIFICATION_BODY: &str = "Repeat current track changed to {repeat_current}";
+const DEFAULT_REPEAT_CURRENT_NOTIFICATION_SUMMARY: &str = "{title}";
+const DEFAULT_REPEAT_CURRENT_NOTIFICATION_TIMEOUT: i32 = 1;
 const DEFAULT_AAAMODE_NOTIFICATION_BODY: &str = "AAA mode changed to {aaa_mode}";
 const DEFAULT_AAAMODE_NOTIFICATION_SUMMARY: &str = "{title}";
 const DEFAULT_AAAMODE_NOTIFICATION_TIMEOUT: i32 = 1;
+const DEFAULT_CONNECTED_NOTIFICATION_BODY: &str = "Connected to cmus";
+const DEFAULT_CONNECTED_NOTIFICATION_SUMMARY: &str = "cmus";
+const DEFAULT_CONNECTED_NOTIFICATION_TIMEOUT: i32 = 2;
+const DEFAULT_DISCONNECTED_NOTIFICATION_BODY: &str = "cmus closed";
+const DEFAULT_DISCONNECTED_NOTIFICATION_SUMMARY: &str = "cmus";
+const DEFAULT_DISCONNECTED_NOTIFICATION_TIMEOUT: i32 = 2;
+const DEFAULT_CONTINUE_NOTIFICATION_BODY: &str = "Continue mode changed to {continue}";
+const DEFAULT_CONTINUE_NOTIFICATION_SUMMARY: &str = "{title}";
+const DEFAULT_CONTINUE_NOTIFICATION_TIMEOUT: i32 = 1;
+const DEFAULT_PLAY_LIBRARY_NOTIFICATION_BODY: &str = "Play library changed to {play_library}";
+const DEFAULT_PLAY_LIBRARY_NOTIFICATION_SUMMARY: &str = "{title}";
+const DEFAULT_PLAY_LIBRARY_NOTIFICATION_TIMEOUT: i32 = 1;
+const DEFAULT_PLAY_SORTED_NOTIFICATION_BODY: &str = "Play sorted changed to {play_sorted}";
+const DEFAULT_PLAY_SORTED_NOTIFICATION_SUMMARY: &str = "{title}";
+const DEFAULT_PLAY_SORTED_NOTIFICATION_TIMEOUT: i32 = 1;
+const DEFAULT_REPLAYGAIN_NOTIFICATION_BODY: &str = "ReplayGain changed to {replaygain} (preamp {replaygain_preamp} dB, limit {replaygain_limit})";
+const DEFAULT_REPLAYGAIN_NOTIFICATION_SUMMARY: &str = "{title}";
+const DEFAULT_REPLAYGAIN_NOTIFICATION_TIMEOUT: i32 = 1;
+const DEFAULT_SOFTVOL_NOTIFICATION_BODY: &str = "Software volume changed to {softvol}";
+const DEFAULT_SOFTVOL_NOTIFICATION_SUMMARY: &str = "{title}";
+const DEFAULT_SOFTVOL_NOTIFICATION_TIMEOUT: i32 = 1;
+const DEFAULT_STREAM_NOTIFICATION_BODY: &str = "<b>Station:</b> {station}";
+const DEFAULT_STREAM_NOTIFICATION_SUMMARY: &str = "{stream}";
+const DEFAULT_STREAM_NOTIFICATION_TIMEOUT: i32 = 5;
+const DEFAULT_SEEK_NOTIFICATION_BODY: &str = "{seek_icon} {prev_position} → {position}";
+const DEFAULT_SEEK_NOTIFICATION_SUMMARY: &str = "{title}";
+const DEFAULT_SEEK_NOTIFICATION_TIMEOUT: i32 = 1;
 #[cfg(feature = "lyrics")]
 const DEFAULT_LYRICS_NOTIFICATION_BODY: &str = "{lyrics}";
 #[cfg(feature = "lyrics")]
@@ -34,6 +84,8 @@ const DEFAULT_LYRICS_NOTIFICATION_SUMMARY: &str = "Lyrics";
 #[derive(Parser, Serialize, Deserialize)]
 #[cfg_attr(feature = "debug", derive(Debug))]
 #[command(author, about, version, long_about = None)]
+// Use the default values for the missing keys, so the old config files keep working when we add new options.
+#[serde(default)]
 pub struct Settings {
     /// The notification timeout, in seconds
     #[arg(short, long)]
@@ -54,26 +106,27 @@ pub struct Settings {
     /// The path to look for the cover image, if not given, the cover will be searched in the track's directory
     /// for an image file with the name "cover".
     ///
-    /// You can use the placeholder "{artist}" and "{album}" and "{title}" and "{track_number}" and
-    /// "{disc_number}" and "{year}" and "{genre}" in the path, they will be replaced with the corresponding metadata.
+    /// You can use the placeholder "{artist}" and "{album}" and "{title}" and "{tracknumber}" and
+    /// "{discnumber}" and "{date}" and "{genre}" in the path, they will be replaced with the corresponding metadata.
     /// but if the metadata is not available, the placeholder will be replaced with an empty string.
-    /// And you can use the simple glob pattern `*` to match any character.
-    /// e.g. "covers/{artist}/{album}/cover.*", "covers/{artist}/{album}/*",
+    /// Also you can use the file placeholders like "{filename}" (see "--summary").
+    /// And you can use the glob patterns, `*` to match any characters, `?` to match one character,
+    /// `[abc]` to match one of the characters, and `**` to match any directories.
+    /// e.g. "covers/{artist}/{album}/cover.*", "covers/{artist}/{album}/*", "~/Pictures/covers/{album}.*",
+    /// the first image file (in the alphabetical order) that matches is used.
     ///
-    /// If you not specify the full path, the cover will be started from the track's directory.
+    /// If you not specify the full path, the cover will be started from the track's directory,
+    /// and "~" is your home directory. If no file matches, the cover is searched in the track's directory.
+    ///
+    /// The old "r#<regex>" last segment is not supported anymore, replace it with a glob pattern,
+    /// e.g. "covers/r#.*\.jpg" becomes "covers/*.jpg".
     #[arg(short = 'w', long = "cover-path")]
     pub cover_path_template: Option<String>,
     #[cfg(feature = "lyrics")]
-    /// The lyrics file path, if not given, the lyrics will be searched in the track's directory
-    /// for a text file with the name "lyrics", or with the same name as the track.
-    ///
-    /// You can use the placeholder "{artist}" and "{album}" and "{title}" and "{track_number}" and
-    /// "{disc_number}" and "{year}" and "{genre}" in the path, they will be replaced with the corresponding metadata.
-    /// but if the metadata is not available, the placeholder will be replaced with an empty string.
-    /// And you can use the simple glob pattern `*` to match any character.
-    /// e.g. "lyrics/{artist}/{album}/{title}.lrc", "lyrics/{artist}/{album}/*",
+    /// The lyrics file path, e.g. "lyrics/{artist}/{album}/{title}.lrc".
     ///
-    /// If you not specify the full path, the lyrics will be started from the track's directory.
+    /// The lyrics files are not looked up yet, so this option has no effect for now,
+    /// and unlike "--cover-path", the path is not a glob pattern.
     #[arg(short = 'y', long)]
     lyrics_path: Option<String>,
     /// The maximum path depth to search for the cover and lyrics files,
@@ -87,55 +140,88 @@ pub struct Settings {
     pub app_name: Option<String>,
     /// The summary of the notification.
     ///
-    /// you can use the placeholder "{artist}" and "{album}" and "{title}" and "{track_number}" and
-    /// "{disc_number}" and "{year}" and "{genre}" in the summary, they will be replaced with the corresponding metadata.
+    /// you can use the placeholder "{artist}" and "{album}" and "{title}" and "{tracknumber}" and
+    /// "{discnumber}" and "{date}" and "{genre}" in the summary, they will be replaced with the corresponding metadata.
     /// but if the metadata is not available, the placeholder will be replaced with an empty string.
+    /// Also the placeholders "{filename}", "{stem}", "{dirname}", "{parent_dir}", "{ext}" (e.g. "mp3")
+    /// and "{format}" (e.g. "FLAC") are taken from the track path, so they work for the untagged files too.
     /// e.g. "{artist} - {title}"
     #[arg(short, long)]
     summary: Option<String>,
     #[cfg(feature = "lyrics")]
     /// The body of the notification.
     ///
-    /// you can use the placeholder "{artist}" and "{album}" and "{title}" and "{track_number}" and
-    /// "{disc_number}" and "{year}" and "{genre}" in the body, they will be replaced with the corresponding metadata.
+    /// you can use the placeholder "{artist}" and "{album}" and "{title}" and "{tracknumber}" and
+    /// "{discnumber}" and "{date}" and "{genre}" in the body, they will be replaced with the corresponding metadata.
     /// but if the metadata is not available, the placeholder will be replaced with an empty string.
+    /// Also you can use the file placeholders like "{filename}" (see "--summary").
     ///
     /// And you can use the placeholder "{lyrics}" to show the lyrics of the track, if available.
     /// But if you use this placeholder, the notification will be persistent, and you need to dismiss it manually tow times.
     ///
-    /// Also you can use the placeholder "{progress}" to show the progress of the track, in the format "00:00 / 00:00".
+    /// Also you can use the placeholder "{progress}" to show the progress of the track, in the format "1:23 / 4:05",
+    /// and the placeholders "{position}", "{duration}", "{remaining}" and "{percent}" (see "--time-format").
     ///
-    /// Also you can use the placeholder "{progress_bar}" to show the progress bar of the track.
+    /// Also you can use the placeholder "{progress_bar}" to show the progress bar of the track,
+    /// see the "--progress-bar-*" options to customize it.
     ///
-    /// Like the "{lyrics}" placeholder, if you use the "{progress}" or "{progress_bar}" placeholder,
-    /// the notification will be persistent, and you need to dismiss it manually tow times.
+    /// Like the "{lyrics}" placeholder, if you use the "{progress}", "{progress_bar}", "{position}", "{remaining}"
+    /// or "{percent}" placeholder, the notification will be persistent, and you need to dismiss it manually tow times.
+    ///
+    /// And you can use the placeholders prefixed with "prev_" like "{prev_title}" to get the previous track metadata,
+    /// e.g. "Skipped {prev_title}, now playing {title}".
+    ///
+    /// The placeholders support a default value "{album|Unknown album}", and filters like "{title:upper:truncate(40)}"
+    /// (upper, lower, trim, truncate(n), escape_markup, raw), and you can show a part only if a placeholder has a value
+    /// with "{#album} — {album}{/album}", or only if it doesn't with "{^album}No album{/album}".
+    /// Use "{{" and "}}" for the literal braces, and "\n" and "\t" for a new line and a tab (in all the templates).
     ///
     /// Also you can use the simple html markup, if your notification server supports it.
+    /// The placeholder values are escaped, so a tag like "Rock & Roll" doesn't break the markup,
+    /// unless you use the "raw" filter, e.g. "{comment:raw}".
     #[arg()]
     body: Option<String>,
     #[cfg(not(feature = "lyrics"))]
     /// The body of the notification.
     ///
-    /// you can use the placeholder "{artist}" and "{album}" and "{title}" and "{track_number}" and
-    /// "{disc_number}" and "{year}" and "{genre}" in the body, they will be replaced with the corresponding metadata.
+    /// you can use the placeholder "{artist}" and "{album}" and "{title}" and "{tracknumber}" and
+    /// "{discnumber}" and "{date}" and "{genre}" in the body, they will be replaced with the corresponding metadata.
     /// but if the metadata is not available, the placeholder will be replaced with an empty string.
+    /// Also you can use the file placeholders like "{filename}" (see "--summary").
     ///
-    /// And you can use the placeholder "{progress}" to show the progress of the track, in the format "00:00 / 00:00".
-    /// Also you can use the placeholder "{progress_bar}" to show the progress bar of the track.
+    /// And you can use the placeholder "{progress}" to show the progress of the track, in the format "1:23 / 4:05",
+    /// and the placeholders "{position}", "{duration}", "{remaining}" and "{percent}" (see "--time-format").
+    /// Also you can use the placeholder "{progress_bar}" to show the progress bar of the track,
+    /// see the "--progress-bar-*" options to customize it.
     ///
-    /// But if you use the "{progress}" or "{progress_bar}" placeholder,
+    /// But if you use the "{progress}", "{progress_bar}", "{position}", "{remaining}" or "{percent}" placeholder,
     /// the notification will be persistent, and you need to dismiss it manually tow times.
     ///
+    /// And you can use the placeholders prefixed with "prev_" like "{prev_title}" to get the previous track metadata,
+    /// e.g. "Skipped {prev_title}, now playing {title}".
+    ///
+    /// The placeholders support a default value "{album|Unknown album}", and filters like "{title:upper:truncate(40)}"
+    /// (upper, lower, trim, truncate(n), escape_markup, raw), and you can show a part only if a placeholder has a value
+    /// with "{#album} — {album}{/album}", or only if it doesn't with "{^album}No album{/album}".
+    /// Use "{{" and "}}" for the literal braces, and "\n" and "\t" for a new line and a tab (in all the templates).
+    ///
     /// Also you can use the simple html markup, if your notification server supports it.
+    /// The placeholder values are escaped, so a tag like "Rock & Roll" doesn't break the markup,
+    /// unless you use the "raw" filter, e.g. "{comment:raw}".
     #[arg()]
     body: Option<String>,
     /// The cmus-remote binary path, if not given, the program will search for it in the PATH environment variable.
     ///
     /// if you're using a custom package format like flatpak, or snap, you can give it the full run command (without any arguments),
     /// e.g. "flatpak run io.github.cmus.cmus", "snap run cmus"
+    ///
+    /// By default the program talks to the cmus socket directly, and doesn't run cmus-remote at all,
+    /// the command will be used only if you change it to something other than "cmus-remote".
     #[arg(short = 'b', long = "cmus-remote-bin")]
     cmus_remote_bin_path: Option<String>,
     /// The cmus socket address, if not given, the program will use the default socket address, which is "$XDG_RUNTIME_DIR/cmus-socket".
+    ///
+    /// It can be a path to a Unix socket, or a "host:port" TCP address (requires the socket password).
     #[arg(short = 'k', long = "cmus-socket")]
     pub cmus_socket_address: Option<String>,
     /// The cmus socket password, if any.
@@ -147,8 +233,11 @@ pub struct Settings {
     ///  e.g. if the track duration is 3 minutes, the program will request the cmus status once, and wait for 3 minutes to make another request,
     /// and if the track changes to another track with 5 minutes duration, the program will request the cmus status once, and wait for 5 minutes to make another request at so on.
     ///
+    /// While cmus is paused or stopped, the program will poll it with a short interval that grows up to a few seconds,
+    /// and after every change it will poll fast for a while, because more changes usually follow (e.g. skipping some tracks).
+    ///
     /// this is useful if you have a potato computer, and you don't want to waste your CPU and battery,
-    /// but it will make the notification a little bit stupid, if you change the track manually, the notification will not update until the track duration time is reached.
+    /// but it will make the notification a little bit stupid, if you change the track manually after a while, the notification will not update until the track duration time is reached.
     ///
     /// but I recommend 1s, it's not too fast, and not too slow, and it will not waste your CPU and battery.
     #[arg(short = 'r', long)]
@@ -156,6 +245,25 @@ pub struct Settings {
     /// Link the program with cmus, if the cmus are not running, the program will exit.
     #[arg(short = 'l', long)]
     pub link: bool,
+    /// How long the linked program keeps trying to reach cmus before it exits, in milliseconds.
+    ///
+    /// This is useful to survive the transient failures, and to wait for cmus to start if the program started first.
+    #[arg(long)]
+    link_grace_period: Option<u64>,
+    /// Don't poll cmus, instead wait for cmus to tell the program about the status changes.
+    ///
+    /// To make this work, set the program as the cmus status display program,
+    /// by running `:set status_display_program=cmus-notify` in cmus.
+    /// cmus doesn't run the status display program when the player settings (e.g. the volume) change,
+    /// so these changes will be noticed with the next status change.
+    /// The program still polls cmus every `--interval` while a live notification (e.g. "{progress}") is visible,
+    /// and every `--link-grace-period` in the `--link` mode, to know when cmus quits.
+    #[arg(short = 'e', long)]
+    pub event_driven: bool,
+    /// Refuse to start if any template has an error, like an unknown placeholder (e.g. "{artsit}"),
+    /// instead of just printing a warning.
+    #[arg(long)]
+    pub strict: bool,
     /// Force the program to use the external cover file, if available, and not even try to get the cover from the track's metadata.
     /// this is useful if you have a cover file with a better quality than the cover in the track's metadata.
     #[arg(short = 'u', long)]
@@ -167,15 +275,95 @@ pub struct Settings {
     /// No use the external cover file, even if it's available and the track's metadata doesn't have a cover.
     #[arg(short = 'n', long)]
     pub no_use_external_cover: bool,
+    /// The embedded picture types to use as the cover, in the order of preference, the default is "front", "media", "any".
+    ///
+    /// The types are "other", "icon", "other_icon", "front", "back", "leaflet", "media", "lead_artist", "artist",
+    /// "conductor", "band", "composer", "lyricist", "recording_location", "during_recording", "during_performance",
+    /// "screen_capture", "fish", "illustration", "band_logo" and "publisher_logo", and "any" matches all of them.
+    /// If no embedded picture matches, the external cover is used (unless `--no-use-external-cover`).
+    /// You can give this option multiple times.
+    #[arg(long = "cover-picture-type")]
+    cover_picture_types: Option<Vec<String>>,
+    /// The minimum width and height of the embedded cover, in pixels, the smaller pictures (e.g. the icons) are skipped.
+    /// It's 0 by default, so every picture is accepted, e.g. set it to 64 to skip the 32x32 file icons.
+    #[arg(long)]
+    cover_min_size: Option<u32>,
+    /// The maximum width and height of the cover sent to the notification server, in pixels.
+    /// The larger covers are shrunk (keeping their aspect ratio), so they don't make huge D-Bus messages.
+    #[arg(long)]
+    cover_max_size: Option<u32>,
+    /// The resampling filter used to shrink the cover, "nearest", "triangle", "catmull_rom", "gaussian" or "lanczos3".
+    ///
+    /// "nearest" is the fastest, and "lanczos3" is the sharpest but the slowest.
+    #[arg(long)]
+    cover_resize_filter: Option<String>,
+    /// Crop the cover to a square, from its center, e.g. for the servers that stretch the images.
+    #[arg(long)]
+    pub cover_square: bool,
+    /// Don't cache the covers in "$XDG_CACHE_HOME/cmus-notify/covers".
+    ///
+    /// The embedded covers are cached by the album, so the tracks of an album show their cover instantly,
+    /// and the external ones by their search (the rendered `--cover-path`, the track directory and name).
+    /// The embedded covers are given to the notification server as a file instead of the pixels,
+    /// and a cached cover is used until its source file (the track, or the external cover) changes.
+    #[arg(long)]
+    pub no_cover_cache: bool,
     #[cfg(feature = "lyrics")]
     /// No use the external lyrics file, even if it's available and the track's metadata doesn't have a lyrics.
     #[arg(short = 'o', long)]
     pub no_use_external_lyrics: bool,
+    /// The patterns to fill the missing tags of the untagged tracks from their path,
+    /// e.g. "{tracknumber} - {artist} - {title}", or with the directories "{artist}/{album}/{tracknumber} - {title}".
+    ///
+    /// The patterns are matched against the end of the track path, without the extension, and the first one that matches is used,
+    /// so put the more specific patterns first.
+    /// The tags that exist in the track are never replaced.
+    /// You can give this option multiple times.
+    #[arg(long = "filename-pattern")]
+    filename_patterns: Option<Vec<String>>,
+    /// The format of the time placeholders, "{position}", "{duration}", "{remaining}" and "{progress}".
+    ///
+    /// "%h", "%m" and "%s" are the hours, minutes and seconds, and "%H", "%M" and "%S" are the same but padded to two digits.
+    /// If the format doesn't have the hours, the minutes are the total minutes, e.g. "%m:%S".
+    /// By default, the time is formatted as "m:ss", or "h:mm:ss" if it's an hour or longer.
+    #[arg(long)]
+    time_format: Option<String>,
+    /// The commands of the "{exec:NAME}" placeholders, as "NAME=COMMAND", e.g. "rating=my-rating-script".
+    ///
+    /// The command runs with `sh -c`, and its output (without the trailing new lines) replaces the placeholder,
+    /// e.g. "{exec:rating|Not rated}". The track fields are in the environment, "$CMUS_FILE", "$CMUS_STATUS",
+    /// "$CMUS_DURATION", "$CMUS_POSITION", "$CMUS_STREAM", and the tags like "$CMUS_TITLE" and "$CMUS_ARTIST".
+    /// The command runs once per track, and if it fails or doesn't finish in time (see "--exec-timeout"),
+    /// the placeholder is empty.
+    /// Only the configured commands can run, and you can give this option multiple times.
+    #[arg(long = "exec")]
+    exec_commands: Option<Vec<String>>,
+    /// How long the "{exec:NAME}" commands can run, in milliseconds, before they're killed.
+    #[arg(long)]
+    exec_timeout: Option<u64>,
+    /// The width of the "{progress_bar}" placeholder, in characters.
+    #[arg(long)]
+    progress_bar_width: Option<u16>,
+    /// The character of the played part of the "{progress_bar}".
+    #[arg(long)]
+    progress_bar_fill: Option<String>,
+    /// The character of the remaining part of the "{progress_bar}".
+    #[arg(long)]
+    progress_bar_empty: Option<String>,
+    /// The character of the current position in the "{progress_bar}", between the played and the remaining parts.
+    /// If you leave it empty, the position will not be marked.
+    #[arg(long)]
+    progress_bar_head: Option<String>,
+    /// Show the played percentage after the "{progress_bar}", e.g. "━━━━●─────── 35%".
+    #[arg(long)]
+    pub progress_bar_percentage: bool,
     /// Show the player notifications, like if you change the shuffle mode, or the repeat mode, or the volume.
     #[arg(short = 'g', long)]
     pub show_player_notifications: bool,
     /// The volume change notification body.
     /// you can use the placeholders like "{volume}" in the body, it will be replaced with the shuffle mode.
+    /// And you can use the placeholders prefixed with "prev_" like "{prev_volume}" to get the value before the change,
+    /// e.g. "Volume {prev_volume}% → {volume}%".
     ///
     /// If you leave it empty, the notification will not be shown.
     #[arg(short = 'B', long)]
@@ -212,6 +400,18 @@ pub struct Settings {
     /// The time out of the repeat mode change notification, in seconds.
     #[arg(short = 'H', long)]
     repeat_notification_timeout: Option<i32>,
+    /// The repeat current track mode change notification body.
+    /// you can use the placeholders like "{repeat_current}" in the body, it will be replaced with the repeat current track mode.
+    ///
+    /// If you leave it empty, the notification will not be shown.
+    #[arg(long)]
+    repeat_current_notification_body: Option<String>,
+    /// The repeat current track mode change notification summary.
+    #[arg(long)]
+    repeat_current_notification_summary: Option<String>,
+    /// The time out of the repeat current track mode change notification, in seconds.
+    #[arg(long)]
+    repeat_current_notification_timeout: Option<i32>,
     /// The aaa mode change notification body.
     /// you can use the placeholders like "{aaa_mode}" in the body, it will be replaced with the aaa mode.
     ///
@@ -250,6 +450,115 @@ pub struct Settings {
     /// The time out of the status change notification, in seconds.
     #[arg(short = 'Q', long)]
     status_notification_timeout: Option<i32>,
+    /// The cmus connected notification body, it's shown when the program connects to cmus, e.g. when cmus starts.
+    /// you can use the track placeholders like "{title}" and the player settings placeholders like "{volume}" in the body.
+    ///
+    /// If you leave it empty, the notification will not be shown.
+    #[arg(long)]
+    connected_notification_body: Option<String>,
+    /// The cmus connected notification summary.
+    #[arg(long)]
+    connected_notification_summary: Option<String>,
+    /// The time out of the cmus started (the program connected to cmus) notification, in seconds.
+    #[arg(long)]
+    connected_notification_timeout: Option<i32>,
+    /// The cmus disconnected notification body, it's shown when the program loses the connection to cmus, e.g. when cmus closes.
+    /// you can use the placeholders of the last known track like "{prev_title}" in the body.
+    ///
+    /// If you leave it empty, the notification will not be shown.
+    #[arg(long)]
+    disconnected_notification_body: Option<String>,
+    /// The cmus disconnected notification summary.
+    #[arg(long)]
+    disconnected_notification_summary: Option<String>,
+    /// The time out of the cmus closed (the program lost the connection to cmus) notification, in seconds.
+    #[arg(long)]
+    disconnected_notification_timeout: Option<i32>,
+    /// The continue mode change notification body.
+    /// you can use the placeholders like "{continue}" in the body, it will be replaced with the continue mode.
+    ///
+    /// If you leave it empty, the notification will not be shown.
+    #[arg(long)]
+    continue_notification_body: Option<String>,
+    /// The continue mode change notification summary.
+    #[arg(long)]
+    continue_notification_summary: Option<String>,
+    /// The time out of the continue mode change notification, in seconds.
+    #[arg(long)]
+    continue_notification_timeout: Option<i32>,
+    /// The play library mode change notification body.
+    /// you can use the placeholders like "{play_library}" in the body, it will be replaced with the play library mode.
+    ///
+    /// If you leave it empty, the notification will not be shown.
+    #[arg(long)]
+    play_library_notification_body: Option<String>,
+    /// The play library mode change notification summary.
+    #[arg(long)]
+    play_library_notification_summary: Option<String>,
+    /// The time out of the play library mode change notification, in seconds.
+    #[arg(long)]
+    play_library_notification_timeout: Option<i32>,
+    /// The play sorted mode change notification body.
+    /// you can use the placeholders like "{play_sorted}" in the body, it will be replaced with the play sorted mode.
+    ///
+    /// If you leave it empty, the notification will not be shown.
+    #[arg(long)]
+    play_sorted_notification_body: Option<String>,
+    /// The play sorted mode change notification summary.
+    #[arg(long)]
+    play_sorted_notification_summary: Option<String>,
+    /// The time out of the play sorted mode change notification, in seconds.
+    #[arg(long)]
+    play_sorted_notification_timeout: Option<i32>,
+    /// The ReplayGain settings change notification body.
+    /// you can use the placeholders like "{replaygain}", "{replaygain_limit}" and "{replaygain_preamp}" in the body, they will be replaced with the ReplayGain settings.
+    ///
+    /// If you leave it empty, the notification will not be shown.
+    #[arg(long)]
+    replaygain_notification_body: Option<String>,
+    /// The ReplayGain settings change notification summary.
+    #[arg(long)]
+    replaygain_notification_summary: Option<String>,
+    /// The time out of the ReplayGain settings change notification, in seconds.
+    #[arg(long)]
+    replaygain_notification_timeout: Option<i32>,
+    /// The software volume mode change notification body.
+    /// you can use the placeholders like "{softvol}" in the body, it will be replaced with the software volume mode.
+    ///
+    /// If you leave it empty, the notification will not be shown.
+    #[arg(long)]
+    softvol_notification_body: Option<String>,
+    /// The software volume mode change notification summary.
+    #[arg(long)]
+    softvol_notification_summary: Option<String>,
+    /// The time out of the software volume mode change notification, in seconds.
+    #[arg(long)]
+    softvol_notification_timeout: Option<i32>,
+    /// The internet radio stream notification body.
+    /// It is shown instead of the track notification when a stream starts, and when the song on the station changes.
+    /// you can use the placeholders "{stream}" (the current song) and "{station}" (the station name) in the body.
+    ///
+    /// If you leave it empty, the notification will not be shown.
+    #[arg(long)]
+    stream_notification_body: Option<String>,
+    /// The internet radio stream notification summary.
+    #[arg(long)]
+    stream_notification_summary: Option<String>,
+    /// The time out of the internet radio stream notification, in seconds.
+    #[arg(long)]
+    stream_notification_timeout: Option<i32>,
+    /// The seek notification body.
+    /// It is shown when you jump forward or backward in the track. you can use the placeholders "{seek_icon}" (⏩ or ⏪), "{prev_position}" and "{position}" in the body.
+    ///
+    /// If you leave it empty, the notification will not be shown.
+    #[arg(long)]
+    seek_notification_body: Option<String>,
+    /// The seek notification summary.
+    #[arg(long)]
+    seek_notification_summary: Option<String>,
+    /// The time out of the seek notification, in seconds.
+    #[arg(long)]
+    seek_notification_timeout: Option<i32>,
     #[cfg(feature = "docs")]
     #[arg(long, hide = true)]
     #[serde(skip)]
@@ -258,6 +567,19 @@ pub struct Settings {
     #[arg(long = "config")]
     #[serde(skip)]
     config_path: Option<String>,
+    /// The parsed templates, by their config key, so we don't parse them on every notification.
+    /// They're parsed at startup, or the first time they're asked for.
+    #[arg(skip)]
+    #[serde(skip)]
+    compiled_templates: RefCell<HashMap<&'static str, Rc<Template>>>,
+    /// The parsed filename patterns, the invalid ones are skipped.
+    #[arg(skip)]
+    #[serde(skip)]
+    compiled_filename_patterns: Vec<FilenamePattern>,
+    /// The configured commands, with the cache of their outputs.
+    #[arg(skip)]
+    #[serde(skip)]
+    compiled_exec_commands: ExecCommands,
 }
 
 impl Default for Settings {
@@ -279,12 +601,30 @@ impl Default for Settings {
             cmus_socket_password: None,
             interval: Some(DEFAULT_INTERVAL_TIME),
             link: false,
+            link_grace_period: Some(DEFAULT_LINK_GRACE_PERIOD),
+            event_driven: false,
+            strict: false,
             force_use_external_cover: false,
             #[cfg(feature = "lyrics")]
             force_use_external_lyrics: false,
             no_use_external_cover: false,
             #[cfg(feature = "lyrics")]
             no_use_external_lyrics: false,
+            cover_picture_types: None,
+            cover_min_size: Some(DEFAULT_COVER_MIN_SIZE),
+            cover_max_size: Some(DEFAULT_COVER_MAX_SIZE),
+            cover_resize_filter: Some(DEFAULT_COVER_RESIZE_FILTER.to_string()),
+            cover_square: false,
+            no_cover_cache: false,
+            filename_patterns: None,
+            time_format: None,
+            exec_commands: None,
+            exec_timeout: Some(DEFAULT_EXEC_TIMEOUT),
+            progress_bar_width: Some(DEFAULT_PROGRESS_BAR_WIDTH),
+            progress_bar_fill: Some(DEFAULT_PROGRESS_BAR_FILL.to_string()),
+            progress_bar_empty: Some(DEFAULT_PROGRESS_BAR_EMPTY.to_string()),
+            progress_bar_head: Some(DEFAULT_PROGRESS_BAR_HEAD.to_string()),
+            progress_bar_percentage: false,
             show_player_notifications: false,
             volume_notification_body: Some(DEFAULT_VOLUME_CHANGE_NOTIFICATION_BODY.to_string()),
             volume_notification_summary: Some(
@@ -297,6 +637,9 @@ impl Default for Settings {
             repeat_notification_body: Some(DEFAULT_REPEAT_NOTIFICATION_BODY.to_string()),
             repeat_notification_summary: Some(DEFAULT_REPEAT_NOTIFICATION_SUMMARY.to_string()),
             repeat_notification_timeout: Some(DEFAULT_REPEAT_NOTIFICATION_TIMEOUT),
+            repeat_current_notification_body: Some(DEFAULT_REPEAT_CURRENT_NOTIFICATION_BODY.to_string()),
+            repeat_current_notification_summary: Some(DEFAULT_REPEAT_CURRENT_NOTIFICATION_SUMMARY.to_string()),
+            repeat_current_notification_timeout: Some(DEFAULT_REPEAT_CURRENT_NOTIFICATION_TIMEOUT),
             aaa_mode_notification_body: Some(DEFAULT_AAAMODE_NOTIFICATION_BODY.to_string()),
             aaa_mode_notification_summary: Some(DEFAULT_AAAMODE_NOTIFICATION_SUMMARY.to_string()),
             aaa_mode_notification_timeout: Some(DEFAULT_AAAMODE_NOTIFICATION_TIMEOUT),
@@ -309,9 +652,39 @@ impl Default for Settings {
                 DEFAULT_STATUS_CHANGE_NOTIFICATION_SUMMARY.to_string(),
             ),
             status_notification_timeout: Some(DEFAULT_STATUS_CHANGE_NOTIFICATION_TIMEOUT),
+            connected_notification_body: Some(DEFAULT_CONNECTED_NOTIFICATION_BODY.to_string()),
+            connected_notification_summary: Some(DEFAULT_CONNECTED_NOTIFICATION_SUMMARY.to_string()),
+            connected_notification_timeout: Some(DEFAULT_CONNECTED_NOTIFICATION_TIMEOUT),
+            disconnected_notification_body: Some(DEFAULT_DISCONNECTED_NOTIFICATION_BODY.to_string()),
+            disconnected_notification_summary: Some(DEFAULT_DISCONNECTED_NOTIFICATION_SUMMARY.to_string()),
+            disconnected_notification_timeout: Some(DEFAULT_DISCONNECTED_NOTIFICATION_TIMEOUT),
+            continue_notification_body: Some(DEFAULT_CONTINUE_NOTIFICATION_BODY.to_string()),
+            continue_notification_summary: Some(DEFAULT_CONTINUE_NOTIFICATION_SUMMARY.to_string()),
+            continue_notification_timeout: Some(DEFAULT_CONTINUE_NOTIFICATION_TIMEOUT),
+            play_library_notification_body: Some(DEFAULT_PLAY_LIBRARY_NOTIFICATION_BODY.to_string()),
+            play_library_notification_summary: Some(DEFAULT_PLAY_LIBRARY_NOTIFICATION_SUMMARY.to_string()),
+            play_library_notification_timeout: Some(DEFAULT_PLAY_LIBRARY_NOTIFICATION_TIMEOUT),
+            play_sorted_notification_body: Some(DEFAULT_PLAY_SORTED_NOTIFICATION_BODY.to_string()),
+            play_sorted_notification_summary: Some(DEFAULT_PLAY_SORTED_NOTIFICATION_SUMMARY.to_string()),
+            play_sorted_notification_timeout: Some(DEFAULT_PLAY_SORTED_NOTIFICATION_TIMEOUT),
+            replaygain_notification_body: Some(DEFAULT_REPLAYGAIN_NOTIFICATION_BODY.to_string()),
+            replaygain_notification_summary: Some(DEFAULT_REPLAYGAIN_NOTIFICATION_SUMMARY.to_string()),
+            replaygain_notification_timeout: Some(DEFAULT_REPLAYGAIN_NOTIFICATION_TIMEOUT),
+            softvol_notification_body: Some(DEFAULT_SOFTVOL_NOTIFICATION_BODY.to_string()),
+            softvol_notification_summary: Some(DEFAULT_SOFTVOL_NOTIFICATION_SUMMARY.to_string()),
+            softvol_notification_timeout: Some(DEFAULT_SOFTVOL_NOTIFICATION_TIMEOUT),
+            stream_notification_body: Some(DEFAULT_STREAM_NOTIFICATION_BODY.to_string()),
+            stream_notification_summary: Some(DEFAULT_STREAM_NOTIFICATION_SUMMARY.to_string()),
+            stream_notification_timeout: Some(DEFAULT_STREAM_NOTIFICATION_TIMEOUT),
+            seek_notification_body: Some(DEFAULT_SEEK_NOTIFICATION_BODY.to_string()),
+            seek_notification_summary: Some(DEFAULT_SEEK_NOTIFICATION_SUMMARY.to_string()),
+            seek_notification_timeout: Some(DEFAULT_SEEK_NOTIFICATION_TIMEOUT),
             #[cfg(feature = "docs")]
             markdown_help: false,
             config_path: None,
+            compiled_templates: RefCell::default(),
+            compiled_filename_patterns: Vec::new(),
+            compiled_exec_commands: ExecCommands::default(),
         }
     }
 }
@@ -388,6 +761,9 @@ impl Settings {
         cfg.cmus_socket_password = args.cmus_socket_password.or(cfg.cmus_socket_password);
         cfg.interval = args.interval.or(cfg.interval);
         cfg.link = args.link || cfg.link;
+        cfg.link_grace_period = args.link_grace_period.or(cfg.link_grace_period);
+        cfg.event_driven = args.event_driven || cfg.event_driven;
+        cfg.strict = args.strict || cfg.strict;
         cfg.force_use_external_cover =
             args.force_use_external_cover || cfg.force_use_external_cover;
         #[cfg(feature = "lyrics")]
@@ -397,6 +773,21 @@ impl Settings {
             cfg.no_use_external_lyrics = args.no_use_external_lyrics || cfg.no_use_external_lyrics;
         }
         cfg.no_use_external_cover = args.no_use_external_cover || cfg.no_use_external_cover;
+        cfg.cover_picture_types = args.cover_picture_types.or(cfg.cover_picture_types);
+        cfg.cover_min_size = args.cover_min_size.or(cfg.cover_min_size);
+        cfg.cover_max_size = args.cover_max_size.or(cfg.cover_max_size);
+        cfg.cover_resize_filter = args.cover_resize_filter.or(cfg.cover_resize_filter);
+        cfg.cover_square = args.cover_square || cfg.cover_square;
+        cfg.no_cover_cache = args.no_cover_cache || cfg.no_cover_cache;
+        cfg.filename_patterns = args.filename_patterns.or(cfg.filename_patterns);
+        cfg.time_format = args.time_format.or(cfg.time_format);
+        cfg.exec_commands = args.exec_commands.or(cfg.exec_commands);
+        cfg.exec_timeout = args.exec_timeout.or(cfg.exec_timeout);
+        cfg.progress_bar_width = args.progress_bar_width.or(cfg.progress_bar_width);
+        cfg.progress_bar_fill = args.progress_bar_fill.or(cfg.progress_bar_fill);
+        cfg.progress_bar_empty = args.progress_bar_empty.or(cfg.progress_bar_empty);
+        cfg.progress_bar_head = args.progress_bar_head.or(cfg.progress_bar_head);
+        cfg.progress_bar_percentage = args.progress_bar_percentage || cfg.progress_bar_percentage;
         cfg.show_player_notifications =
             args.show_player_notifications || cfg.show_player_notifications;
         cfg.volume_notification_body = args
@@ -426,6 +817,15 @@ impl Settings {
         cfg.repeat_notification_timeout = args
             .repeat_notification_timeout
             .or(cfg.repeat_notification_timeout);
+        cfg.repeat_current_notification_body = args
+            .repeat_current_notification_body
+            .or(cfg.repeat_current_notification_body);
+        cfg.repeat_current_notification_summary = args
+            .repeat_current_notification_summary
+            .or(cfg.repeat_current_notification_summary);
+        cfg.repeat_current_notification_timeout = args
+            .repeat_current_notification_timeout
+            .or(cfg.repeat_current_notification_timeout);
         cfg.aaa_mode_notification_body = args
             .aaa_mode_notification_body
             .or(cfg.aaa_mode_notification_body);
@@ -435,6 +835,91 @@ impl Settings {
         cfg.aaa_mode_notification_timeout = args
             .aaa_mode_notification_timeout
             .or(cfg.aaa_mode_notification_timeout);
+        cfg.connected_notification_body = args
+            .connected_notification_body
+            .or(cfg.connected_notification_body);
+        cfg.connected_notification_summary = args
+            .connected_notification_summary
+            .or(cfg.connected_notification_summary);
+        cfg.connected_notification_timeout = args
+            .connected_notification_timeout
+            .or(cfg.connected_notification_timeout);
+        cfg.disconnected_notification_body = args
+            .disconnected_notification_body
+            .or(cfg.disconnected_notification_body);
+        cfg.disconnected_notification_summary = args
+            .disconnected_notification_summary
+            .or(cfg.disconnected_notification_summary);
+        cfg.disconnected_notification_timeout = args
+            .disconnected_notification_timeout
+            .or(cfg.disconnected_notification_timeout);
+        cfg.continue_notification_body = args
+            .continue_notification_body
+            .or(cfg.continue_notification_body);
+        cfg.continue_notification_summary = args
+            .continue_notification_summary
+            .or(cfg.continue_notification_summary);
+        cfg.continue_notification_timeout = args
+            .continue_notification_timeout
+            .or(cfg.continue_notification_timeout);
+        cfg.play_library_notification_body = args
+            .play_library_notification_body
+            .or(cfg.play_library_notification_body);
+        cfg.play_library_notification_summary = args
+            .play_library_notification_summary
+            .or(cfg.play_library_notification_summary);
+        cfg.play_library_notification_timeout = args
+            .play_library_notification_timeout
+            .or(cfg.play_library_notification_timeout);
+        cfg.play_sorted_notification_body = args
+            .play_sorted_notification_body
+            .or(cfg.play_sorted_notification_body);
+        cfg.play_sorted_notification_summary = args
+            .play_sorted_notification_summary
+            .or(cfg.play_sorted_notification_summary);
+        cfg.play_sorted_notification_timeout = args
+            .play_sorted_notification_timeout
+            .or(cfg.play_sorted_notification_timeout);
+        cfg.replaygain_notification_body = args
+            .replaygain_notification_body
+            .or(cfg.replaygain_notification_body);
+        cfg.replaygain_notification_summary = args
+            .replaygain_notification_summary
+            .or(cfg.replaygain_notification_summary);
+        cfg.replaygain_notification_timeout = args
+            .replaygain_notification_timeout
+            .or(cfg.replaygain_notification_timeout);
+        cfg.softvol_notification_body = args
+            .softvol_notification_body
+            .or(cfg.softvol_notification_body);
+        cfg.softvol_notification_summary = args
+            .softvol_notification_summary
+            .or(cfg.softvol_notification_summary);
+        cfg.softvol_notification_timeout = args
+            .softvol_notification_timeout
+            .or(cfg.softvol_notification_timeout);
+        cfg.stream_notification_body = args
+            .stream_notification_body
+            .or(cfg.stream_notification_body);
+        cfg.stream_notification_summary = args
+            .stream_notification_summary
+            .or(cfg.stream_notification_summary);
+        cfg.stream_notification_timeout = args
+            .stream_notification_timeout
+            .or(cfg.stream_notification_timeout);
+        cfg.seek_notification_body = args
+            .seek_notification_body
+            .or(cfg.seek_notification_body);
+        cfg.seek_notification_summary = args
+            .seek_notification_summary
+            .or(cfg.seek_notification_summary);
+        cfg.seek_notification_timeout = args
+            .seek_notification_timeout
+            .or(cfg.seek_notification_timeout);
+
+        cfg.compile_templates();
+        cfg.compile_filename_patterns();
+        cfg.compile_exec_commands();
 
         #[cfg(feature = "debug")]
         info!("The final settings: {:?}", cfg);
@@ -442,6 +927,64 @@ impl Settings {
         cfg
     }
 
+    /// Parse all the templates once, so the notifications only render them.
+    pub fn compile_templates(&mut self) {
+        *self.compiled_templates.get_mut() = self
+            .templates()
+            .into_iter()
+            .map(|(key, source)| (key, Rc::new(Template::compile(&source))))
+            .collect();
+    }
+
+    /// Parse the filename patterns once, the invalid ones are reported by `validate_templates`.
+    pub fn compile_filename_patterns(&mut self) {
+        self.compiled_filename_patterns = self
+            .filename_patterns
+            .iter()
+            .flatten()
+            .filter_map(|pattern| FilenamePattern::from_str(pattern).ok())
+            .collect();
+    }
+
+    #[inline(always)]
+    pub fn filename_patterns(&self) -> &[FilenamePattern] {
+        &self.compiled_filename_patterns
+    }
+
+    /// Build the "{exec:NAME}" commands once, the invalid ones are reported by `validate_templates`.
+    pub fn compile_exec_commands(&mut self) {
+        self.compiled_exec_commands = ExecCommands::new(
+            self.exec_commands.as_deref().unwrap_or_default(),
+            std::time::Duration::from_millis(self.exec_timeout()),
+        );
+    }
+
+    #[inline(always)]
+    pub fn exec_commands(&self) -> &ExecCommands {
+        &self.compiled_exec_commands
+    }
+
+    /// Returns the parsed template of the config key (e.g. "body"), see `templates`,
+    /// it's parsed and kept the first time it's asked for, if the templates are not compiled yet.
+    /// If the key has no template, it's empty.
+    pub fn template(&self, key: &str) -> Rc<Template> {
+        if let Some(template) = self.compiled_templates.borrow().get(key) {
+            return Rc::clone(template);
+        }
+        let Some((key, source)) = self
+            .templates()
+            .into_iter()
+            .find(|(template_key, _)| *template_key == key)
+        else {
+            return Rc::new(Template::compile(""));
+        };
+        let template = Rc::new(Template::compile(&source));
+        self.compiled_templates
+            .borrow_mut()
+            .insert(key, Rc::clone(&template));
+        template
+    }
+
     #[inline(always)]
     pub fn timeout(&self) -> i32 {
         self.timeout.unwrap_or(NOTIFICATION_TIMEOUT)
@@ -455,11 +998,195 @@ impl Settings {
             .to_string()
     }
 
+    /// Returns all the templates, with their config keys.
+    pub fn templates(&self) -> Vec<(&'static str, String)> {
+        let mut templates = vec![
+            ("summary", self.summary()),
+            ("body", self.body()),
+            ("status_notification_summary", self.status_notification_summary()),
+            ("status_notification_body", self.status_notification_body()),
+            ("volume_notification_summary", self.volume_notification_summary()),
+            ("volume_notification_body", self.volume_notification_body()),
+            ("shuffle_notification_summary", self.shuffle_notification_summary()),
+            ("shuffle_notification_body", self.shuffle_notification_body()),
+            ("repeat_notification_summary", self.repeat_notification_summary()),
+            ("repeat_notification_body", self.repeat_notification_body()),
+            ("repeat_current_notification_summary", self.repeat_current_notification_summary()),
+            ("repeat_current_notification_body", self.repeat_current_notification_body()),
+            ("aaa_mode_notification_summary", self.aaa_mode_notification_summary()),
+            ("aaa_mode_notification_body", self.aaa_mode_notification_body()),
+            ("connected_notification_summary", self.connected_notification_summary()),
+            ("connected_notification_body", self.connected_notification_body()),
+            ("disconnected_notification_summary", self.disconnected_notification_summary()),
+            ("disconnected_notification_body", self.disconnected_notification_body()),
+            ("continue_notification_summary", self.continue_notification_summary()),
+            ("continue_notification_body", self.continue_notification_body()),
+            ("play_library_notification_summary", self.play_library_notification_summary()),
+            ("play_library_notification_body", self.play_library_notification_body()),
+            ("play_sorted_notification_summary", self.play_sorted_notification_summary()),
+            ("play_sorted_notification_body", self.play_sorted_notification_body()),
+            ("replaygain_notification_summary", self.replaygain_notification_summary()),
+            ("replaygain_notification_body", self.replaygain_notification_body()),
+            ("softvol_notification_summary", self.softvol_notification_summary()),
+            ("softvol_notification_body", self.softvol_notification_body()),
+            ("stream_notification_summary", self.stream_notification_summary()),
+            ("stream_notification_body", self.stream_notification_body()),
+            ("seek_notification_summary", self.seek_notification_summary()),
+            ("seek_notification_body", self.seek_notification_body()),
+        ];
+        #[cfg(feature = "lyrics")]
+        templates.extend([
+            ("lyrics_notification_summary", self.lyrics_notification_summary()),
+            ("lyrics_notification_body", self.lyrics_notification_body()),
+        ]);
+        if let Some(template) = &self.cover_path_template {
+            templates.push(("cover_path_template", template.clone()));
+        }
+        #[cfg(feature = "lyrics")]
+        if let Some(template) = &self.lyrics_path {
+            templates.push(("lyrics_path", template.clone()));
+        }
+        templates
+    }
+
+    /// Parse all the templates and the filename patterns, and check their placeholders.
+    /// Returns the problems found, every one of them points at the config key of the template.
+    pub fn validate_templates(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (key, template) in self.templates() {
+            match Template::from_str(&template) {
+                Ok(template) => {
+                    for placeholder in template.keys() {
+                        let current = placeholder
+                            .strip_prefix(crate::PREVIOUS_STATE_PREFIX)
+                            .unwrap_or(placeholder);
+                        if let Some(name) = current.strip_prefix(exec::EXEC_PREFIX) {
+                            if !self.exec_commands().contains(name) {
+                                problems.push(format!(
+                                    "\"{key}\": unknown command \"{{{placeholder}}}\""
+                                ));
+                            }
+                        } else if !crate::is_known_placeholder(placeholder) {
+                            problems.push(format!(
+                                "\"{key}\": unknown placeholder \"{{{placeholder}}}\""
+                            ));
+                        }
+                    }
+                }
+                Err(e) => problems.push(format!("\"{key}\": {e}")),
+            }
+        }
+        if let Some(template) = &self.cover_path_template {
+            if template.split('/').any(|segment| segment.contains("r#")) {
+                problems.push(
+                    "\"cover_path_template\": the \"r#<regex>\" segment is not supported anymore, \
+                     use a glob pattern instead, e.g. \"covers/{album}/*.jpg\""
+                        .to_string(),
+                );
+            }
+        }
+        for pattern in self.filename_patterns.iter().flatten() {
+            match FilenamePattern::from_str(pattern) {
+                Ok(pattern) => {
+                    for key in pattern.keys() {
+                        if !TrackMetadata::KNOWN_TAGS.contains(&key.as_str()) {
+                            problems.push(format!(
+                                "\"filename_patterns\": unknown tag \"{{{key}}}\""
+                            ));
+                        }
+                    }
+                }
+                Err(e) => problems.push(format!("\"filename_patterns\": {e}")),
+            }
+        }
+        for picture_type in self.cover_picture_types.iter().flatten() {
+            if let Err(e) = embedded_art::parse_picture_type(picture_type) {
+                problems.push(format!("\"cover_picture_types\": {e}"));
+            }
+        }
+        if let Some(Err(e)) = self.cover_resize_filter.as_deref().map(cover_resize::parse_filter) {
+            problems.push(format!("\"cover_resize_filter\": {e}"));
+        }
+        for definition in self.exec_commands.iter().flatten() {
+            if let Err(e) = exec::parse_definition(definition) {
+                problems.push(format!("\"exec_commands\": {e}"));
+            }
+        }
+        problems
+    }
+
     #[inline(always)]
     pub fn interval(&self) -> u64 {
         self.interval.unwrap_or(DEFAULT_INTERVAL_TIME)
     }
 
+    #[inline(always)]
+    pub fn link_grace_period(&self) -> u64 {
+        self.link_grace_period.unwrap_or(DEFAULT_LINK_GRACE_PERIOD)
+    }
+
+    #[inline(always)]
+    pub fn exec_timeout(&self) -> u64 {
+        self.exec_timeout.unwrap_or(DEFAULT_EXEC_TIMEOUT)
+    }
+
+    #[inline(always)]
+    pub fn time_format(&self) -> Option<&str> {
+        self.time_format.as_deref()
+    }
+
+    #[inline(always)]
+    pub fn progress_bar_width(&self) -> u16 {
+        self.progress_bar_width.unwrap_or(DEFAULT_PROGRESS_BAR_WIDTH)
+    }
+
+    #[inline(always)]
+    pub fn progress_bar_fill(&self) -> &str {
+        self.progress_bar_fill.as_deref().unwrap_or(DEFAULT_PROGRESS_BAR_FILL)
+    }
+
+    #[inline(always)]
+    pub fn progress_bar_empty(&self) -> &str {
+        self.progress_bar_empty.as_deref().unwrap_or(DEFAULT_PROGRESS_BAR_EMPTY)
+    }
+
+    #[inline(always)]
+    pub fn progress_bar_head(&self) -> &str {
+        self.progress_bar_head.as_deref().unwrap_or(DEFAULT_PROGRESS_BAR_HEAD)
+    }
+
+    /// Returns the embedded picture types to use as the cover, in order, `None` matches any type.
+    /// The unknown types are skipped, they're reported by `validate_templates`.
+    pub fn cover_picture_types(&self) -> Vec<Option<u8>> {
+        let names = match &self.cover_picture_types {
+            Some(names) => names.iter().map(String::as_str).collect(),
+            None => DEFAULT_COVER_PICTURE_TYPES.to_vec(),
+        };
+        names
+            .into_iter()
+            .filter_map(|name| embedded_art::parse_picture_type(name).ok())
+            .collect()
+    }
+
+    #[inline(always)]
+    pub fn cover_min_size(&self) -> u32 {
+        self.cover_min_size.unwrap_or(DEFAULT_COVER_MIN_SIZE)
+    }
+
+    /// Returns how the cover is resized, an unknown filter is replaced with the default one,
+    /// it's reported by `validate_templates`.
+    pub fn cover_resize(&self) -> CoverResize {
+        let filter = cover_resize::parse_filter(
+            self.cover_resize_filter.as_deref().unwrap_or(DEFAULT_COVER_RESIZE_FILTER),
+        )
+        .unwrap_or(image::imageops::FilterType::Triangle); // The default one.
+        CoverResize {
+            max_size: self.cover_max_size.unwrap_or(DEFAULT_COVER_MAX_SIZE),
+            filter,
+            square: self.cover_square,
+        }
+    }
+
     #[inline(always)]
     pub fn depth(&self) -> u8 {
         self.depth.unwrap_or(DEFAULT_MAX_DEPTH)
@@ -577,6 +1304,28 @@ impl Settings {
             .unwrap_or(DEFAULT_REPEAT_NOTIFICATION_TIMEOUT)
     }
 
+    #[inline(always)]
+    pub fn repeat_current_notification_summary(&self) -> String {
+        self.repeat_current_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_REPEAT_CURRENT_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn repeat_current_notification_body(&self) -> String {
+        self.repeat_current_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_REPEAT_CURRENT_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn repeat_current_notification_timeout(&self) -> i32 {
+        self.repeat_current_notification_timeout
+            .unwrap_or(DEFAULT_REPEAT_CURRENT_NOTIFICATION_TIMEOUT)
+    }
+
     #[inline(always)]
     pub fn aaa_mode_notification_summary(&self) -> String {
         self.aaa_mode_notification_summary
@@ -598,6 +1347,222 @@ impl Settings {
         self.aaa_mode_notification_timeout
             .unwrap_or(DEFAULT_AAAMODE_NOTIFICATION_TIMEOUT)
     }
+
+    #[inline(always)]
+    pub fn connected_notification_summary(&self) -> String {
+        self.connected_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_CONNECTED_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn connected_notification_body(&self) -> String {
+        self.connected_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_CONNECTED_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn connected_notification_timeout(&self) -> i32 {
+        self.connected_notification_timeout
+            .unwrap_or(DEFAULT_CONNECTED_NOTIFICATION_TIMEOUT)
+    }
+
+    #[inline(always)]
+    pub fn disconnected_notification_summary(&self) -> String {
+        self.disconnected_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_DISCONNECTED_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn disconnected_notification_body(&self) -> String {
+        self.disconnected_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_DISCONNECTED_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn disconnected_notification_timeout(&self) -> i32 {
+        self.disconnected_notification_timeout
+            .unwrap_or(DEFAULT_DISCONNECTED_NOTIFICATION_TIMEOUT)
+    }
+
+    #[inline(always)]
+    pub fn continue_notification_summary(&self) -> String {
+        self.continue_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_CONTINUE_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn continue_notification_body(&self) -> String {
+        self.continue_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_CONTINUE_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn continue_notification_timeout(&self) -> i32 {
+        self.continue_notification_timeout
+            .unwrap_or(DEFAULT_CONTINUE_NOTIFICATION_TIMEOUT)
+    }
+
+    #[inline(always)]
+    pub fn play_library_notification_summary(&self) -> String {
+        self.play_library_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_PLAY_LIBRARY_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn play_library_notification_body(&self) -> String {
+        self.play_library_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_PLAY_LIBRARY_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn play_library_notification_timeout(&self) -> i32 {
+        self.play_library_notification_timeout
+            .unwrap_or(DEFAULT_PLAY_LIBRARY_NOTIFICATION_TIMEOUT)
+    }
+
+    #[inline(always)]
+    pub fn play_sorted_notification_summary(&self) -> String {
+        self.play_sorted_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_PLAY_SORTED_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn play_sorted_notification_body(&self) -> String {
+        self.play_sorted_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_PLAY_SORTED_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn play_sorted_notification_timeout(&self) -> i32 {
+        self.play_sorted_notification_timeout
+            .unwrap_or(DEFAULT_PLAY_SORTED_NOTIFICATION_TIMEOUT)
+    }
+
+    #[inline(always)]
+    pub fn replaygain_notification_summary(&self) -> String {
+        self.replaygain_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_REPLAYGAIN_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn replaygain_notification_body(&self) -> String {
+        self.replaygain_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_REPLAYGAIN_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn replaygain_notification_timeout(&self) -> i32 {
+        self.replaygain_notification_timeout
+            .unwrap_or(DEFAULT_REPLAYGAIN_NOTIFICATION_TIMEOUT)
+    }
+
+    #[inline(always)]
+    pub fn softvol_notification_summary(&self) -> String {
+        self.softvol_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_SOFTVOL_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn softvol_notification_body(&self) -> String {
+        self.softvol_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_SOFTVOL_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn softvol_notification_timeout(&self) -> i32 {
+        self.softvol_notification_timeout
+            .unwrap_or(DEFAULT_SOFTVOL_NOTIFICATION_TIMEOUT)
+    }
+
+    #[inline(always)]
+    pub fn stream_notification_summary(&self) -> String {
+        self.stream_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_STREAM_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn stream_notification_body(&self) -> String {
+        self.stream_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_STREAM_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn stream_notification_timeout(&self) -> i32 {
+        self.stream_notification_timeout
+            .unwrap_or(DEFAULT_STREAM_NOTIFICATION_TIMEOUT)
+    }
+
+    #[inline(always)]
+    pub fn seek_notification_summary(&self) -> String {
+        self.seek_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_SEEK_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[cfg(feature = "lyrics")]
+    #[inline(always)]
+    pub fn lyrics_notification_body(&self) -> String {
+        self.lyrics_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_LYRICS_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[cfg(feature = "lyrics")]
+    #[inline(always)]
+    pub fn lyrics_notification_summary(&self) -> String {
+        self.lyrics_notification_summary
+            .as_ref()
+            .unwrap_or(&DEFAULT_LYRICS_NOTIFICATION_SUMMARY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn seek_notification_body(&self) -> String {
+        self.seek_notification_body
+            .as_ref()
+            .unwrap_or(&DEFAULT_SEEK_NOTIFICATION_BODY.to_string())
+            .to_string()
+    }
+
+    #[inline(always)]
+    pub fn seek_notification_timeout(&self) -> i32 {
+        self.seek_notification_timeout
+            .unwrap_or(DEFAULT_SEEK_NOTIFICATION_TIMEOUT)
+    }
 }
 
 #[cfg(test)]
@@ -609,4 +1574,138 @@ mod tests {
         use clap::CommandFactory;
         Settings::command().debug_assert();
     }
+
+    #[test]
+    fn test_load_an_old_config_without_the_new_keys() {
+        let config_path = std::env::temp_dir().join(format!("cmus-notify-config-{}.toml", std::process::id()));
+        std::fs::write(&config_path, "timeout = 3\npersistent = true\n").unwrap();
+
+        let settings: Result<Settings, _> = confy::load_path(&config_path);
+        let _ = std::fs::remove_file(&config_path);
+
+        let settings = settings.expect("The old config must still load");
+        assert_eq!(settings.timeout(), 3);
+        assert!(settings.persistent);
+        assert!(!settings.event_driven);
+        assert_eq!(settings.interval(), DEFAULT_INTERVAL_TIME);
+    }
+
+    #[test]
+    fn test_the_default_templates_are_valid() {
+        assert_eq!(Settings::default().validate_templates(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_compile_the_templates_once() {
+        let settings = Settings::default();
+
+        assert!(Rc::ptr_eq(&settings.template("body"), &settings.template("body")));
+        assert_eq!(*settings.template("summary"), Template::compile(NOTIFICATION_SUMMARY));
+        // The parsed arguments are not compiled yet, so the template is parsed the first time it's asked for.
+        let settings = Settings::parse_from(["cmus-notify", "{artist}"]);
+        assert_eq!(*settings.template("body"), Template::compile("{artist}"));
+        assert!(Rc::ptr_eq(&settings.template("body"), &settings.template("body")));
+        let settings = Settings {
+            summary: Some("{title}".to_string()),
+            ..Settings::default()
+        };
+        assert_eq!(*settings.template("summary"), Template::compile("{title}"));
+    }
+
+    #[test]
+    fn test_report_the_template_problems_with_their_config_keys() {
+        let settings = Settings {
+            summary: Some("{artsit} - {prev_title}".to_string()),
+            volume_notification_body: Some("{#volume}{volume}".to_string()),
+            ..Settings::default()
+        };
+
+        assert_eq!(
+            settings.validate_templates(),
+            vec![
+                "\"summary\": unknown placeholder \"{artsit}\"".to_string(),
+                "\"volume_notification_body\": Unclosed section: volume".to_string(),
+            ]
+        );
+    }
+
+    #[cfg(feature = "lyrics")]
+    #[test]
+    fn test_validate_the_lyrics_notification_templates() {
+        let settings = Settings {
+            lyrics_notification_body: Some("{lyrcs}".to_string()),
+            ..Settings::default()
+        };
+
+        assert_eq!(
+            settings.validate_templates(),
+            vec!["\"lyrics_notification_body\": unknown placeholder \"{lyrcs}\"".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_report_the_unknown_commands() {
+        let mut settings = Settings {
+            summary: Some("{exec:rating} {exec:playcount}".to_string()),
+            exec_commands: Some(vec!["rating=echo 5".to_string(), "echo 10".to_string()]),
+            ..Settings::default()
+        };
+        settings.compile_exec_commands();
+
+        assert_eq!(
+            settings.validate_templates(),
+            vec![
+                "\"summary\": unknown command \"{exec:playcount}\"".to_string(),
+                "\"exec_commands\": Invalid command \"echo 10\", it should be like \"NAME=COMMAND\"".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_the_unknown_cover_picture_types() {
+        let settings = Settings {
+            cover_picture_types: Some(vec!["front".to_string(), "poster".to_string()]),
+            ..Settings::default()
+        };
+
+        assert_eq!(
+            settings.validate_templates(),
+            vec![format!("\"cover_picture_types\": {}", embedded_art::parse_picture_type("poster").unwrap_err())]
+        );
+        assert_eq!(settings.cover_picture_types(), vec![Some(embedded_art::FRONT_COVER)]);
+        assert_eq!(Settings::default().cover_min_size(), 0);
+    }
+
+    #[test]
+    fn test_warn_about_the_old_regex_cover_path() {
+        let settings = Settings {
+            cover_path_template: Some("covers/{album}/r#.*\\.jpg".to_string()),
+            ..Settings::default()
+        };
+
+        let problems = settings.validate_templates();
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("\"cover_path_template\": the \"r#<regex>\" segment"));
+        assert!(Settings {
+            cover_path_template: Some("covers/{album}/*.jpg".to_string()),
+            ..Settings::default()
+        }
+        .validate_templates()
+        .is_empty());
+    }
+
+    #[test]
+    fn test_report_the_unknown_cover_resize_filter() {
+        let settings = Settings {
+            cover_resize_filter: Some("bicubic".to_string()),
+            ..Settings::default()
+        };
+
+        assert_eq!(
+            settings.validate_templates(),
+            vec![format!("\"cover_resize_filter\": {}", cover_resize::parse_filter("bicubic").unwrap_err())]
+        );
+        assert_eq!(settings.cover_resize().filter, image::imageops::FilterType::Triangle);
+    }
 }
@@ -0,0 +1,208 @@
+//! Support for running as the cmus `status_display_program`.
+//!
+//! cmus runs its status display program on every status change, with the status as arguments,
+//! e.g. `cmus-notify status playing file /path/to/track.mp3 artist ... duration 284`.
+//! When this happens, we forward the arguments to the running daemon through a Unix socket,
+//! so the daemon can react to the change instantly, instead of polling cmus.
+use crate::cmus::Track;
+#[cfg(feature = "debug")]
+use log::{debug, info};
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// The separator between the forwarded arguments.
+const SEPARATOR: char = '\0';
+
+/// Returns true if the program is invoked by cmus as its status display program.
+/// cmus always passes the status as the first two arguments, e.g. `status playing`.
+#[inline(always)]
+pub fn is_status_display_invocation(args: &[String]) -> bool {
+    args.get(1).is_some_and(|arg| arg == "status")
+}
+
+/// The path of the socket the daemon listens on, "$XDG_RUNTIME_DIR/cmus-notify-socket".
+/// If there's no runtime directory, the socket will be created in the temporary directory.
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => PathBuf::from(runtime_dir).join("cmus-notify-socket"),
+        None => std::env::temp_dir().join(format!(
+            "cmus-notify-{}-socket",
+            std::env::var("USER").unwrap_or_default()
+        )),
+    }
+}
+
+/// Forward the status display arguments (without the program name) to the running daemon.
+pub fn forward(args: &[String]) -> std::io::Result<()> {
+    let mut stream = UnixStream::connect(socket_path())?;
+    stream.write_all(args.join(&SEPARATOR.to_string()).as_bytes())?;
+    stream.flush()
+}
+
+/// Listen for the status updates forwarded by the status display program.
+pub struct StatusDisplayListener {
+    path: PathBuf,
+    updates: Receiver<Vec<String>>,
+}
+
+impl StatusDisplayListener {
+    /// Bind the daemon socket, and start accepting the forwarded updates in the background.
+    pub fn bind() -> std::io::Result<Self> {
+        let path = socket_path();
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AddrInUse,
+                    format!("Another daemon is listening on \"{}\"", path.display()),
+                ));
+            }
+            // The socket is left behind by a daemon that didn't exit cleanly.
+            std::fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        #[cfg(feature = "debug")]
+        info!("Listening for the status display updates on {:?}", path);
+
+        let (sender, updates) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue; };
+                let mut message = String::new();
+                if stream.read_to_string(&mut message).is_err() {
+                    continue;
+                }
+                #[cfg(feature = "debug")]
+                debug!("Received a status display update: {:?}", message);
+                let args = message.split(SEPARATOR).map(str::to_string).collect();
+                if sender.send(args).is_err() {
+                    break; // The daemon is gone.
+                }
+            }
+        });
+
+        Ok(Self { path, updates })
+    }
+
+    /// Block until the next update, or until the timeout is reached.
+    /// Without a timeout, this will wait forever.
+    ///
+    /// Returns the forwarded arguments, or `None` if the timeout is reached.
+    pub fn wait(&self, timeout: Option<Duration>) -> Option<Vec<String>> {
+        let update = match timeout {
+            Some(timeout) => match self.updates.recv_timeout(timeout) {
+                Ok(update) => Some(update),
+                Err(RecvTimeoutError::Timeout) => return None,
+                Err(RecvTimeoutError::Disconnected) => None,
+            },
+            None => self.updates.recv().ok(),
+        };
+        // If the listener thread is dead, we have nothing to wait for, so don't spin.
+        if update.is_none() {
+            std::thread::sleep(timeout.unwrap_or(Duration::from_secs(1)));
+        }
+        update
+    }
+}
+
+impl Drop for StatusDisplayListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Convert the status display arguments into the same format as the `cmus-remote -Q` output,
+/// so it can be parsed into a `Track`.
+///
+/// cmus doesn't tell the status display program the position, so it's the position of the previous track,
+/// if it's the same file (e.g. the playback is paused), or 0 for a new track.
+/// Otherwise every pause would look like a seek to the start.
+pub fn to_query_output(args: &[String], previous: Option<&Track>) -> String {
+    let mut status = String::new();
+    let mut file = String::new();
+    let mut duration = String::from("0");
+    let mut stream = String::new();
+    let mut tags = String::new();
+
+    let mut args = args.iter();
+    while let (Some(key), Some(value)) = (args.next(), args.next()) {
+        match key.as_str() {
+            "status" => status = value.clone(),
+            "file" | "url" => file = value.clone(),
+            "duration" => duration = value.clone(),
+            "stream" => stream = format!("stream {value}\n"),
+            _ => tags.push_str(&format!("tag {key} {value}\n")),
+        }
+    }
+
+    let position = previous
+        .filter(|previous| previous.path == file)
+        .map_or(0, |previous| previous.position);
+
+    format!(
+        "status {status}\nfile {file}\nduration {duration}\nposition {position}\n{tags}{stream}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmus::TrackStatus;
+    use std::str::FromStr;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split('|').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_detect_the_status_display_invocation() {
+        assert!(is_status_display_invocation(&args(
+            "cmus-notify|status|playing|file|/a.mp3"
+        )));
+        assert!(!is_status_display_invocation(&args("cmus-notify|--link")));
+        assert!(!is_status_display_invocation(&args("cmus-notify")));
+    }
+
+    #[test]
+    fn test_parse_the_status_display_arguments_into_a_track() {
+        let output = to_query_output(&args(
+            "status|paused|file|/Music/08 - Photograph.mp3|artist|Alex Goot|title|Photograph|duration|284",
+        ), None);
+        let track = Track::from_str(&output).unwrap();
+
+        assert_eq!(track.status, TrackStatus::Paused);
+        assert_eq!(track.path, "/Music/08 - Photograph.mp3");
+        assert_eq!(track.duration, 284);
+        assert_eq!(track.position, 0);
+        assert_eq!(track.metadata.get("artist"), Some("Alex Goot"));
+        assert_eq!(track.metadata.get("title"), Some("Photograph"));
+    }
+
+    #[test]
+    fn test_keep_the_position_of_the_same_track() {
+        let previous = Track::from_str(&to_query_output(
+            &args("status|playing|file|/Music/a.mp3|duration|284"),
+            None,
+        ))
+        .map(|mut track| {
+            track.position = 120;
+            track
+        })
+        .unwrap();
+
+        let paused = to_query_output(
+            &args("status|paused|file|/Music/a.mp3|duration|284"),
+            Some(&previous),
+        );
+        let next = to_query_output(
+            &args("status|playing|file|/Music/b.mp3|duration|200"),
+            Some(&previous),
+        );
+
+        assert_eq!(Track::from_str(&paused).unwrap().position, 120);
+        assert_eq!(Track::from_str(&next).unwrap().position, 0);
+    }
+}
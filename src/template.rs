@@ -0,0 +1,518 @@
+//! A small template language, used by the notifications, and the cover and lyrics path templates.
+//!
+//! * `{key}` - replaced with the value of the key, or with nothing if it's not available.
+//! * `{key|default}` - replaced with the default text, if the value is not available or empty.
+//! * `{key:filter:filter(arg)}` - the value passes through the filters, from left to right.
+//!   The filters are `upper`, `lower`, `trim`, `truncate(n)`, `escape_markup` and `raw`,
+//!   they don't change the default text.
+//! * `{#key}...{/key}` - the section is rendered only if the value is available, and not empty or "false".
+//! * `{^key}...{/key}` - the inverted section, it's rendered only if the first one is not.
+//!
+//! e.g. `{title:truncate(40)}{#album} — {album}{/album}`, `{artist:upper|Unknown artist}`.
+//!
+//! The key `exec:name` (e.g. `{exec:rating}`) is replaced with the output of a configured command,
+//! the filters follow its name.
+//!
+//! When the template is rendered as markup (e.g. the notification body), the values are escaped,
+//! unless the placeholder has the `raw` filter, e.g. `{comment:raw}`. The template text itself is never escaped.
+//!
+//! Use `{{` and `}}` for the literal braces, and `\n`, `\t` and `\\` for a new line, a tab and a backslash.
+use crate::cmus::TemplateProcessor;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, PartialEq, Error)]
+pub enum TemplateError {
+    #[error("Unclosed placeholder at {0}")]
+    UnclosedPlaceholder(usize),
+    #[error("Empty placeholder at {0}")]
+    EmptyPlaceholder(usize),
+    #[error("Unknown or invalid filter: {0}")]
+    InvalidFilter(String),
+    #[error("Unclosed section: {0}")]
+    UnclosedSection(String),
+    #[error("Unexpected end of section: {0}")]
+    UnexpectedSectionEnd(String),
+}
+
+/// A parsed template, ready to be rendered.
+#[derive(PartialEq, Clone, Default)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(PartialEq, Clone)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+enum Node {
+    Text(String),
+    Placeholder {
+        key: String,
+        filters: Vec<Filter>,
+        default: Option<String>,
+    },
+    Section {
+        key: String,
+        inverted: bool,
+        nodes: Vec<Node>,
+    },
+}
+
+#[derive(PartialEq, Clone)]
+#[cfg_attr(any(feature = "debug", test), derive(Debug))]
+enum Filter {
+    Upper,
+    Lower,
+    Trim,
+    Truncate(usize),
+    EscapeMarkup,
+    /// Don't escape the value when rendering the markup.
+    Raw,
+}
+
+impl FromStr for Filter {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s {
+            "upper" => Ok(Self::Upper),
+            "lower" => Ok(Self::Lower),
+            "trim" => Ok(Self::Trim),
+            "escape_markup" => Ok(Self::EscapeMarkup),
+            "raw" => Ok(Self::Raw),
+            _ => s
+                .strip_prefix("truncate(")
+                .and_then(|arg| arg.strip_suffix(')'))
+                .and_then(|arg| arg.trim().parse().ok())
+                .map(Self::Truncate)
+                .ok_or_else(|| TemplateError::InvalidFilter(s.to_string())),
+        }
+    }
+}
+
+impl Filter {
+    fn apply(&self, value: String) -> String {
+        match self {
+            Self::Upper => value.to_uppercase(),
+            Self::Lower => value.to_lowercase(),
+            Self::Trim => value.trim().to_string(),
+            Self::Truncate(max) => {
+                if value.chars().count() <= *max {
+                    value
+                } else if *max == 0 {
+                    String::new()
+                } else {
+                    let mut truncated = value.chars().take(max - 1).collect::<String>();
+                    truncated.push('…');
+                    truncated
+                }
+            }
+            Self::EscapeMarkup => escape_markup(&value),
+            Self::Raw => value,
+        }
+    }
+}
+
+/// Escape the characters that have a special meaning in the notification markup.
+pub fn escape_markup(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The escape sequences, and the characters they stand for.
+const ESCAPES: &[(&str, char)] = &[
+    ("{{", '{'),
+    ("}}", '}'),
+    ("\\n", '\n'),
+    ("\\t", '\t'),
+    ("\\\\", '\\'),
+];
+
+/// Replace the backslash escape sequences, e.g. "\\n" with a new line.
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('\\') {
+        unescaped.push_str(&rest[..start]);
+        let special = &rest[start..];
+        match ESCAPES
+            .iter()
+            .find(|(sequence, _)| sequence.starts_with('\\') && special.starts_with(sequence))
+        {
+            Some((sequence, c)) => {
+                unescaped.push(*c);
+                rest = &special[sequence.len()..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &special[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Parse the inside of a placeholder, e.g. `title:upper:truncate(40)` or `album:upper|Unknown album`.
+fn parse_placeholder(content: &str) -> Result<Node, TemplateError> {
+    let (head, default) = match content.split_once('|') {
+        Some((head, default)) => (head, Some(unescape(default))),
+        None => (content, None),
+    };
+    let mut parts = head.split(':');
+    let mut key = parts.next().unwrap_or_default().trim().to_string();
+    // The command placeholders have a colon in their key, e.g. "{exec:lastfm:upper}".
+    if key == "exec" || key == "prev_exec" {
+        if let Some(name) = parts.next() {
+            key = format!("{key}:{}", name.trim());
+        }
+    }
+    let filters = parts.map(Filter::from_str).collect::<Result<_, _>>()?;
+
+    Ok(Node::Placeholder {
+        key,
+        filters,
+        default,
+    })
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The open sections, with the nodes before them.
+        let mut sections: Vec<(String, bool, Vec<Node>)> = Vec::new();
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find(['{', '}', '\\']) {
+            text.push_str(&rest[..start]);
+            let position = s.len() - rest.len() + start;
+            let special = &rest[start..];
+
+            if let Some((sequence, c)) = ESCAPES
+                .iter()
+                .find(|(sequence, _)| special.starts_with(sequence))
+            {
+                text.push(*c);
+                rest = &special[sequence.len()..];
+                continue;
+            }
+            // A lone closing brace, or a backslash that doesn't escape anything, is just a text.
+            if !special.starts_with('{') {
+                text.push_str(&special[..1]);
+                rest = &special[1..];
+                continue;
+            }
+            let after_brace = &special[1..];
+
+            let end = match after_brace.find(['{', '}']) {
+                Some(end) if after_brace[end..].starts_with('}') => end,
+                // Another brace opens before this one closes, so this one is just a text.
+                Some(_) => {
+                    text.push('{');
+                    rest = after_brace;
+                    continue;
+                }
+                None => return Err(TemplateError::UnclosedPlaceholder(position)),
+            };
+            let content = &after_brace[..end];
+            rest = &after_brace[end + 1..];
+
+            if content.trim().is_empty() {
+                return Err(TemplateError::EmptyPlaceholder(position));
+            }
+            if !text.is_empty() {
+                nodes.push(Node::Text(std::mem::take(&mut text)));
+            }
+
+            if let Some(key) = content.strip_prefix('#') {
+                sections.push((key.trim().to_string(), false, std::mem::take(&mut nodes)));
+            } else if let Some(key) = content.strip_prefix('^') {
+                sections.push((key.trim().to_string(), true, std::mem::take(&mut nodes)));
+            } else if let Some(key) = content.strip_prefix('/') {
+                let key = key.trim();
+                match sections.pop() {
+                    Some((open_key, inverted, outer_nodes)) if open_key == key => {
+                        let section_nodes = std::mem::replace(&mut nodes, outer_nodes);
+                        nodes.push(Node::Section {
+                            key: open_key,
+                            inverted,
+                            nodes: section_nodes,
+                        });
+                    }
+                    _ => return Err(TemplateError::UnexpectedSectionEnd(key.to_string())),
+                }
+            } else {
+                nodes.push(parse_placeholder(content)?);
+            }
+        }
+
+        if let Some((key, _, _)) = sections.pop() {
+            return Err(TemplateError::UnclosedSection(key));
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            nodes.push(Node::Text(text));
+        }
+
+        Ok(Self { nodes })
+    }
+}
+
+/// Returns true if the value should show a section, it's available, and not empty or "false".
+#[inline(always)]
+fn is_truthy(value: &Option<String>) -> bool {
+    value
+        .as_deref()
+        .is_some_and(|value| !value.is_empty() && value != "false")
+}
+
+fn render_nodes(
+    nodes: &[Node],
+    values: &(impl TemplateProcessor + ?Sized),
+    markup: bool,
+    out: &mut String,
+) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Placeholder {
+                key,
+                filters,
+                default,
+            } => {
+                let value = match values.value(key) {
+                    Some(value) if !value.is_empty() => value,
+                    // The default value is a part of the template, so it's used as written.
+                    _ => {
+                        out.push_str(default.as_deref().unwrap_or_default());
+                        continue;
+                    }
+                };
+                let escape = markup
+                    && !filters
+                        .iter()
+                        .any(|filter| matches!(filter, Filter::Raw | Filter::EscapeMarkup));
+                let value = filters
+                    .iter()
+                    .fold(value, |value, filter| filter.apply(value));
+                if escape {
+                    out.push_str(&escape_markup(&value));
+                } else {
+                    out.push_str(&value);
+                }
+            }
+            Node::Section {
+                key,
+                inverted,
+                nodes,
+            } => {
+                if is_truthy(&values.value(key)) != *inverted {
+                    render_nodes(nodes, values, markup, out);
+                }
+            }
+        }
+    }
+}
+
+fn collect_keys<'a>(nodes: &'a [Node], keys: &mut Vec<&'a str>) {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Placeholder { key, .. } => keys.push(key),
+            Node::Section { key, nodes, .. } => {
+                keys.push(key);
+                collect_keys(nodes, keys);
+            }
+        }
+    }
+}
+
+impl Template {
+    /// Parse the template, or if it's malformed, use it as a plain text.
+    pub fn compile(source: &str) -> Self {
+        Self::from_str(source).unwrap_or_else(|_| Self {
+            nodes: vec![Node::Text(source.to_string())],
+        })
+    }
+
+    /// Render the template, with the values of the given processor.
+    pub fn render(&self, values: &(impl TemplateProcessor + ?Sized)) -> String {
+        let mut out = String::new();
+        self.render_into(values, false, &mut out);
+        out
+    }
+
+    /// Render the template as markup, with the escaped values of the given processor.
+    pub fn render_markup(&self, values: &(impl TemplateProcessor + ?Sized)) -> String {
+        let mut out = String::new();
+        self.render_into(values, true, &mut out);
+        out
+    }
+
+    /// Render the template into the given buffer, replacing its content.
+    /// This is useful to reuse the same buffer, when the template is rendered again and again.
+    pub fn render_into(
+        &self,
+        values: &(impl TemplateProcessor + ?Sized),
+        markup: bool,
+        out: &mut String,
+    ) {
+        out.clear();
+        render_nodes(&self.nodes, values, markup, out);
+    }
+
+    /// Returns true if the template renders nothing, e.g. its source is empty.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the keys used in the template, in the placeholders and the sections.
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys = Vec::new();
+        collect_keys(&self.nodes, &mut keys);
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    impl TemplateProcessor for HashMap<&str, &str> {
+        fn value(&self, key: &str) -> Option<String> {
+            self.get(key).map(|value| value.to_string())
+        }
+    }
+
+    fn render(template: &str) -> String {
+        let values = HashMap::from([
+            ("title", "Photograph"),
+            ("artist", "Alex Goot"),
+            ("album", ""),
+            ("repeat", "false"),
+            ("markup", "Tom & Jerry <3"),
+        ]);
+        Template::from_str(template).unwrap().render(&values)
+    }
+
+    #[test]
+    fn test_render_the_placeholders_and_the_defaults() {
+        assert_eq!(render("{artist} - {title}"), "Alex Goot - Photograph");
+        assert_eq!(render("{album|Unknown album}"), "Unknown album");
+        assert_eq!(render("{genre|Unknown genre}"), "Unknown genre");
+        assert_eq!(render("[{genre}]"), "[]");
+    }
+
+    #[test]
+    fn test_render_the_sections() {
+        assert_eq!(render("{title}{#album} — {album}{/album}"), "Photograph");
+        assert_eq!(render("{title}{#artist} — {artist}{/artist}"), "Photograph — Alex Goot");
+        assert_eq!(render("{^album}No album{/album}"), "No album");
+        assert_eq!(render("{#repeat}🔁{/repeat}{^repeat}➡{/repeat}"), "➡");
+    }
+
+    #[test]
+    fn test_apply_the_filters() {
+        assert_eq!(render("{title:upper}"), "PHOTOGRAPH");
+        assert_eq!(render("{title:lower:truncate(5)}"), "phot…");
+        assert_eq!(render("{title:truncate(40)}"), "Photograph");
+        assert_eq!(render("{markup:escape_markup}"), "Tom &amp; Jerry &lt;3");
+        // The default is written as is, the filters apply to the values only.
+        assert_eq!(render("{genre:upper|Unknown genre}"), "Unknown genre");
+        assert_eq!(render("{artist:upper|Unknown artist}"), "ALEX GOOT");
+    }
+
+    #[test]
+    fn test_escape_the_values_in_the_markup() {
+        let values = HashMap::from([("album", "Rock & Roll <Live>")]);
+        let render = |template: &str| Template::from_str(template).unwrap().render_markup(&values);
+
+        assert_eq!(
+            render("<b>{album}</b>"),
+            "<b>Rock &amp; Roll &lt;Live&gt;</b>"
+        );
+        assert_eq!(render("<b>{album:raw}</b>"), "<b>Rock & Roll <Live></b>");
+        assert_eq!(
+            render("{album:escape_markup}"),
+            "Rock &amp; Roll &lt;Live&gt;"
+        );
+        assert_eq!(render("{title|<i>Unknown</i>}"), "<i>Unknown</i>");
+    }
+
+    #[test]
+    fn test_replace_the_escape_sequences() {
+        assert_eq!(render("{{\"title\": \"{title}\"}}"), "{\"title\": \"Photograph\"}");
+        assert_eq!(render("{{title}} :-}}"), "{title} :-}");
+        assert_eq!(render("{title}\\n{artist}\\t\\\\"), "Photograph\nAlex Goot\t\\");
+        assert_eq!(render("{album|No\\nalbum}"), "No\nalbum");
+        assert_eq!(render("cover\\.jpg"), "cover\\.jpg");
+    }
+
+    #[test]
+    fn test_keep_the_lone_braces_as_text() {
+        assert_eq!(render("a{b {title}"), "a{b Photograph");
+        assert_eq!(render("a}b"), "a}b");
+    }
+
+    #[test]
+    fn test_reject_the_malformed_templates() {
+        assert_eq!(
+            Template::from_str("{title"),
+            Err(TemplateError::UnclosedPlaceholder(0))
+        );
+        assert_eq!(
+            Template::from_str("{#album}{album}"),
+            Err(TemplateError::UnclosedSection("album".to_string()))
+        );
+        assert_eq!(
+            Template::from_str("{#album}{/artist}"),
+            Err(TemplateError::UnexpectedSectionEnd("artist".to_string()))
+        );
+        assert_eq!(
+            Template::from_str("{title:shout}"),
+            Err(TemplateError::InvalidFilter("shout".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_compile_the_malformed_templates_as_text() {
+        assert_eq!(render("{title}"), "Photograph");
+        assert_eq!(
+            Template::compile("{title").render(&HashMap::new()),
+            "{title"
+        );
+    }
+
+    #[test]
+    fn test_list_the_keys() {
+        let template = Template::from_str("{title}{#album} — {album:upper}{/album}").unwrap();
+
+        assert_eq!(template.keys(), vec!["title", "album", "album"]);
+        assert!(!template.is_empty());
+        assert!(Template::compile("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_the_exec_placeholders() {
+        let template = Template::from_str("{exec:lyrics}{exec:rating:upper|-}").unwrap();
+
+        assert_eq!(template.keys(), vec!["exec:lyrics", "exec:rating"]);
+        assert_eq!(template.render(&HashMap::new()), "-");
+    }
+}